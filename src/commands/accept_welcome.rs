@@ -1,13 +1,15 @@
 use anyhow::{bail, Context, Result};
-use nostr_sdk::nips::nip59;
 use nostr_sdk::prelude::*;
 use serde::Serialize;
 use std::time::Duration;
 
 use crate::config::Config;
 use crate::mdk_helper::MdkContext;
-use crate::nostr_client::NostrClient;
+use crate::nostr_client::{RelayPool, UnreachableRelay};
+use crate::outbox::extra_advertised_inbox_relays;
 use crate::output::print_json;
+use crate::relay_auth::AuthFailure;
+use crate::relays::RelayRole;
 
 const KIND_WELCOME: u16 = 444;
 const KIND_GIFT_WRAP: u16 = 1059;
@@ -19,11 +21,19 @@ struct AcceptOutput {
     group_name: String,
     member_count: u32,
     event_id: String,
+    unreachable_relays: Vec<UnreachableRelay>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    auth_failures: Vec<AuthFailure>,
 }
 
-pub async fn run(config: &Config, event_id: &str) -> Result<()> {
-    let ctx = MdkContext::load(config)?;
-    let nostr = NostrClient::new(&ctx.keys, config.relays.clone()).await?;
+pub async fn run(config: &Config, pool: &mut RelayPool, event_id: &str) -> Result<()> {
+    let ctx = MdkContext::load(config).await?;
+    let nostr = pool.client(
+        ctx.signer(),
+        config.relays_with(&[RelayRole::Inbox, RelayRole::KeyPackages]),
+        config.connect_options()?,
+    );
+    let timeout = Duration::from_secs(FETCH_TIMEOUT_SECS);
 
     let event_id_parsed = EventId::from_hex(event_id)
         .or_else(|_| EventId::from_bech32(event_id))
@@ -33,12 +43,20 @@ pub async fn run(config: &Config, event_id: &str) -> Result<()> {
         .id(event_id_parsed)
         .limit(1);
 
-    let events = nostr
-        .fetch_events(filter, Duration::from_secs(FETCH_TIMEOUT_SECS))
+    let mut events = nostr
+        .fetch_events(RelayRole::Inbox, filter.clone(), timeout)
         .await
         .context("Failed to fetch welcome event")?;
 
-    nostr.disconnect().await;
+    if events.is_empty() {
+        let extra_inbox =
+            extra_advertised_inbox_relays(&nostr, &config.relay_urls(RelayRole::Inbox), &ctx.pubkey())
+                .await;
+        events = nostr
+            .fetch_events_from(&extra_inbox, filter, timeout)
+            .await
+            .context("Failed to fetch welcome event from advertised inbox relays")?;
+    }
 
     let event = events
         .into_iter()
@@ -48,19 +66,20 @@ pub async fn run(config: &Config, event_id: &str) -> Result<()> {
     let kind = event.kind.as_u16();
 
     let (rumor, _sender) = if kind == KIND_GIFT_WRAP {
-        let unwrapped = nip59::extract_rumor(&ctx.keys, &event)
+        let (sender, rumor) = ctx
+            .extract_rumor(&event)
             .await
             .context("Failed to unwrap gift-wrap")?;
 
-        if unwrapped.rumor.kind.as_u16() != KIND_WELCOME {
+        if rumor.kind.as_u16() != KIND_WELCOME {
             bail!(
                 "Gift-wrapped event contains kind {}, expected {}",
-                unwrapped.rumor.kind.as_u16(),
+                rumor.kind.as_u16(),
                 KIND_WELCOME
             );
         }
 
-        (unwrapped.rumor, unwrapped.sender)
+        (rumor, sender)
     } else if kind == KIND_WELCOME {
         let rumor: UnsignedEvent = serde_json::from_str(&event.content)
             .context("Failed to parse welcome rumor from event content")?;
@@ -83,6 +102,8 @@ pub async fn run(config: &Config, event_id: &str) -> Result<()> {
         group_name: welcome.group_name,
         member_count: welcome.member_count,
         event_id: event.id.to_hex(),
+        unreachable_relays: nostr.unreachable(),
+        auth_failures: nostr.auth_failures(),
     };
 
     print_json(output);
@@ -0,0 +1,45 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::groups;
+use crate::mdk_helper::MdkContext;
+use crate::output::print_json;
+
+#[derive(Serialize)]
+struct AliasOutput {
+    alias: String,
+    nostr_group_id: String,
+    removed: bool,
+}
+
+pub async fn run(config: &Config, group_id: &str, name: &str) -> Result<()> {
+    // All-hex aliases would shadow group ID prefixes.
+    if name.is_empty() || name.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("Alias must contain at least one non-hex character: {}", name);
+    }
+
+    let ctx = MdkContext::load(config).await?;
+    let group = groups::lookup(&ctx, config, group_id)?;
+    let nostr_group_id = hex::encode(group.nostr_group_id);
+
+    config.set_alias(name, &nostr_group_id)?;
+
+    print_json(AliasOutput {
+        alias: name.to_string(),
+        nostr_group_id,
+        removed: false,
+    });
+    Ok(())
+}
+
+pub async fn run_remove(config: &Config, name: &str) -> Result<()> {
+    let nostr_group_id = config.remove_alias(name)?;
+
+    print_json(AliasOutput {
+        alias: name.to_string(),
+        nostr_group_id,
+        removed: true,
+    });
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, EffectiveSetting};
+use crate::output::print_json;
+
+#[derive(Serialize)]
+struct ListOutput {
+    config_path: PathBuf,
+    settings: Vec<EffectiveSetting>,
+}
+
+#[derive(Serialize)]
+struct SetOutput {
+    config_path: PathBuf,
+    profile: Option<String>,
+    key: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct UnsetOutput {
+    config_path: PathBuf,
+    profile: Option<String>,
+    key: String,
+    removed: bool,
+}
+
+pub async fn get(config: &Config, key: &str) -> Result<()> {
+    let setting = config
+        .effective_settings()
+        .into_iter()
+        .find(|s| s.key == key)
+        .with_context(|| format!("Unknown setting: {}", key))?;
+
+    print_json(setting);
+    Ok(())
+}
+
+pub async fn list(config: &Config) -> Result<()> {
+    print_json(ListOutput {
+        config_path: config.config_path.clone(),
+        settings: config.effective_settings(),
+    });
+    Ok(())
+}
+
+pub async fn set(config_path: &Path, profile: Option<&str>, key: &str, value: &str) -> Result<()> {
+    Config::set_value(config_path, profile, key, value)?;
+
+    print_json(SetOutput {
+        config_path: config_path.to_path_buf(),
+        profile: profile.map(String::from),
+        key: key.to_string(),
+        value: value.to_string(),
+    });
+    Ok(())
+}
+
+pub async fn unset(config_path: &Path, profile: Option<&str>, key: &str) -> Result<()> {
+    let removed = Config::unset_value(config_path, profile, key)?;
+
+    print_json(UnsetOutput {
+        config_path: config_path.to_path_buf(),
+        profile: profile.map(String::from),
+        key: key.to_string(),
+        removed,
+    });
+    Ok(())
+}
+
+pub async fn validate(config_path: &Path) -> Result<()> {
+    print_json(Config::validate(config_path)?);
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::mdk_helper::load_keys;
+use crate::output::print_json;
+use crate::passphrase::read_new_db_passphrase;
+use crate::storage::{derive_db_key, ensure_salt, passphrase_db_key, DbEncryption};
+
+#[derive(Serialize)]
+struct DbEncryptOutput {
+    db_path: String,
+    db_encryption: DbEncryption,
+}
+
+/// Migrate an unencrypted database to SQLCipher in place. The original is kept
+/// as `<db>.bak` until the config records the new mode.
+pub async fn encrypt(config: &Config, mode: DbEncryption) -> Result<()> {
+    if mode == DbEncryption::None {
+        bail!("Choose an encryption mode: passphrase or identity");
+    }
+    if config.db_encryption != DbEncryption::None {
+        bail!("Database is already configured as encrypted ({:?})", config.db_encryption);
+    }
+    if !config.db_path.exists() {
+        bail!("Database not found: {:?}. Run 'mdk init' first", config.db_path);
+    }
+
+    let key = match mode {
+        DbEncryption::Passphrase => {
+            let passphrase = read_new_db_passphrase()?;
+            ensure_salt(&config.db_path)?;
+            passphrase_db_key(&config.db_path, &passphrase)?
+        }
+        _ => {
+            let keys = match config.signer {
+                Some(_) => None,
+                None => Some(load_keys(config)?),
+            };
+            derive_db_key(config, keys.as_ref(), mode)?.context("No database key derived")?
+        }
+    };
+
+    let tmp_path = config.db_path.with_extension("db.encrypting");
+    if tmp_path.exists() {
+        std::fs::remove_file(&tmp_path)
+            .with_context(|| format!("Failed to remove stale {:?}", tmp_path))?;
+    }
+
+    export_encrypted(&config.db_path, &tmp_path, &key)?;
+    remove_wal_files(&config.db_path);
+
+    let backup_path = config.db_path.with_extension("db.bak");
+    std::fs::rename(&config.db_path, &backup_path)
+        .with_context(|| format!("Failed to back up database to {:?}", backup_path))?;
+
+    if let Err(e) = std::fs::rename(&tmp_path, &config.db_path) {
+        restore_backup(&backup_path, &config.db_path);
+        return Err(e).with_context(|| format!("Failed to replace database: {:?}", config.db_path));
+    }
+
+    if let Err(e) = config.save_db_encryption(mode) {
+        restore_backup(&backup_path, &config.db_path);
+        return Err(e).context("Database left unencrypted");
+    }
+
+    std::fs::remove_file(&backup_path)
+        .with_context(|| format!("Failed to remove backup {:?}", backup_path))?;
+
+    print_json(DbEncryptOutput {
+        db_path: config.db_path.to_string_lossy().to_string(),
+        db_encryption: mode,
+    });
+    Ok(())
+}
+
+fn export_encrypted(plain_path: &Path, encrypted_path: &Path, key: &[u8; 32]) -> Result<()> {
+    let conn = Connection::open(plain_path)
+        .with_context(|| format!("Failed to open database: {:?}", plain_path))?;
+
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))
+        .context("Database is not a readable unencrypted SQLite file")?;
+
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+        .context("Failed to checkpoint database")?;
+
+    let target = encrypted_path.to_string_lossy().replace('\'', "''");
+    conn.execute_batch(&format!(
+        "ATTACH DATABASE '{}' AS encrypted KEY \"x'{}'\";
+         SELECT sqlcipher_export('encrypted');
+         DETACH DATABASE encrypted;",
+        target,
+        hex::encode(key)
+    ))
+    .context("Failed to export encrypted database")?;
+
+    Ok(())
+}
+
+/// Put the unencrypted original back; on failure it stays at `backup_path`.
+fn restore_backup(backup_path: &Path, db_path: &Path) {
+    if let Err(e) = std::fs::rename(backup_path, db_path) {
+        tracing::error!("Failed to restore {:?} from {:?}: {}", db_path, backup_path, e);
+    }
+}
+
+fn remove_wal_files(db_path: &Path) {
+    for suffix in ["-wal", "-shm"] {
+        let mut name = db_path.as_os_str().to_os_string();
+        name.push(suffix);
+        let _ = std::fs::remove_file(name);
+    }
+}
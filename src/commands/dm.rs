@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use mdk_core::prelude::*;
+use mdk_storage_traits::groups::types::{Group, GroupState};
+use mdk_storage_traits::groups::GroupStorage;
+use nostr_sdk::prelude::*;
+use nostr_sdk::ToBech32;
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::mdk_helper::MdkContext;
+use crate::nostr_client::{NostrClient, RelayPool, RelayPublishResult, UnreachableRelay};
+use crate::outbox::RelayLists;
+use crate::output::print_json;
+use crate::relay_auth::AuthFailure;
+use crate::relays::RelayRole;
+
+use super::send::publish_message;
+
+const FETCH_TIMEOUT_SECS: u64 = 10;
+/// Leading characters of the peer's npub used in a DM group's name.
+const DM_NAME_NPUB_CHARS: usize = 16;
+
+#[derive(Serialize)]
+struct WelcomeDelivery {
+    event_id: String,
+    accepted_count: usize,
+    relays: Vec<RelayPublishResult>,
+}
+
+#[derive(Serialize)]
+struct DmOutput {
+    peer_pubkey: String,
+    peer_npub: String,
+    nostr_group_id: String,
+    group_created: bool,
+    key_package_event_id: Option<String>,
+    welcomes: Vec<WelcomeDelivery>,
+    event_id: String,
+    message_length: usize,
+    accepted_count: usize,
+    relays: Vec<RelayPublishResult>,
+    unreachable_relays: Vec<UnreachableRelay>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    auth_failures: Vec<AuthFailure>,
+}
+
+pub async fn run(
+    config: &Config,
+    pool: &mut RelayPool,
+    peer: &str,
+    message: &str,
+    min_acks: usize,
+) -> Result<()> {
+    let ctx = MdkContext::load(config).await?;
+    let peer = PublicKey::parse(peer).context("Invalid peer pubkey (expected npub or hex)")?;
+
+    if peer == ctx.pubkey() {
+        anyhow::bail!("Cannot start a direct message with yourself");
+    }
+
+    let nostr = pool.client(
+        ctx.signer(),
+        config.relays_with(&[RelayRole::Write, RelayRole::KeyPackages]),
+        config.connect_options()?,
+    );
+
+    let (group, key_package_event_id, welcomes) = match find_dm_group(&ctx, &peer)? {
+        Some(group) => (group, None, Vec::new()),
+        None => {
+            let (group, key_package_event_id, welcomes) =
+                create_dm_group(&ctx, config, &nostr, &peer, min_acks).await?;
+            (group, Some(key_package_event_id), welcomes)
+        }
+    };
+    let group_created = key_package_event_id.is_some();
+
+    let result = publish_message(&ctx, config, &nostr, &group, message).await?;
+
+    result.require_acks(min_acks)?;
+
+    let output = DmOutput {
+        peer_pubkey: peer.to_hex(),
+        peer_npub: peer.to_bech32().unwrap_or_default(),
+        nostr_group_id: hex::encode(group.nostr_group_id),
+        group_created,
+        key_package_event_id,
+        welcomes,
+        event_id: result.event_id.to_hex(),
+        message_length: message.len(),
+        accepted_count: result.accepted_count(),
+        relays: result.relays,
+        unreachable_relays: nostr.unreachable(),
+        auth_failures: nostr.auth_failures(),
+    };
+
+    print_json(output);
+    Ok(())
+}
+
+/// An existing group whose only members are us and `peer`.
+fn find_dm_group(ctx: &MdkContext, peer: &PublicKey) -> Result<Option<Group>> {
+    let groups = ctx.mdk.get_groups().context("Failed to get groups")?;
+
+    for group in groups {
+        let members = ctx
+            .mdk
+            .get_members(&group.mls_group_id)
+            .context("Failed to get group members")?;
+        if members.len() == 2 && members.contains(peer) && members.contains(&ctx.pubkey()) {
+            return Ok(Some(group));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Create a two-member group from the peer's newest key package and deliver
+/// the gift-wrapped welcome to the peer's inbox relays. The group is only
+/// committed locally once the welcome is delivered; otherwise it is marked
+/// inactive, so it is not found by name, and the next `dm` starts over.
+async fn create_dm_group(
+    ctx: &MdkContext,
+    config: &Config,
+    nostr: &NostrClient,
+    peer: &PublicKey,
+    min_acks: usize,
+) -> Result<(Group, String, Vec<WelcomeDelivery>)> {
+    let relay_lists = RelayLists::fetch(nostr, peer).await?;
+    let timeout = Duration::from_secs(FETCH_TIMEOUT_SECS);
+
+    let filter = Filter::new()
+        .kind(Kind::MlsKeyPackage)
+        .author(*peer)
+        .limit(10);
+
+    let mut key_packages = nostr
+        .fetch_events(RelayRole::KeyPackages, filter.clone(), timeout)
+        .await
+        .context("Failed to fetch peer key package")?;
+    let peer_key_package_relays = relay_lists.key_package_relays();
+    match nostr.fetch_events_from(&peer_key_package_relays, filter, timeout).await {
+        Ok(events) => key_packages.extend(events),
+        Err(e) => tracing::warn!("Failed to fetch key packages from peer's relays: {}", e),
+    }
+
+    let key_package = key_packages
+        .into_iter()
+        .max_by_key(|e| e.created_at)
+        .with_context(|| {
+            format!(
+                "No key package found for {}; they need to run publish-key-package first",
+                peer.to_bech32().unwrap_or_else(|_| peer.to_hex())
+            )
+        })?;
+    let key_package_event_id = key_package.id.to_hex();
+
+    let group_config = NostrGroupConfigData::new(
+        dm_group_name(peer),
+        String::new(),
+        None,
+        None,
+        None,
+        config.relay_urls(RelayRole::Write),
+        vec![ctx.pubkey(), *peer],
+    );
+
+    let created = ctx
+        .mdk
+        .create_group(&ctx.pubkey(), vec![key_package], group_config)
+        .context("Failed to create MLS group")?;
+
+    let mut inbox = relay_lists.inbox_relays();
+    if inbox.is_empty() {
+        tracing::warn!("Peer has no published inbox relays; sending welcome to our write relays");
+        inbox = config.relay_urls(RelayRole::Write);
+    }
+
+    let delivery = async {
+        let mut welcomes = Vec::new();
+        for rumor in created.welcome_rumors {
+            let gift_wrap = ctx
+                .gift_wrap(peer, rumor)
+                .await
+                .context("Failed to gift-wrap welcome")?;
+            let result = nostr.publish_to(&inbox, gift_wrap).await?;
+            result.require_acks(min_acks)?;
+            welcomes.push(WelcomeDelivery {
+                event_id: result.event_id.to_hex(),
+                accepted_count: result.accepted_count(),
+                relays: result.relays,
+            });
+        }
+        anyhow::Ok(welcomes)
+    };
+    let welcomes = match delivery.await {
+        Ok(welcomes) => welcomes,
+        Err(e) => {
+            discard_group(ctx, &created.group);
+            return Err(e);
+        }
+    };
+
+    ctx.mdk
+        .merge_pending_commit(&created.group.mls_group_id)
+        .context("Failed to merge group creation commit")?;
+
+    Ok((created.group, key_package_event_id, welcomes))
+}
+
+/// Retire a DM group whose welcome never reached the peer.
+fn discard_group(ctx: &MdkContext, group: &Group) {
+    let mut group = group.clone();
+    group.state = GroupState::Inactive;
+    if let Err(e) = ctx.mdk.storage().save_group(group) {
+        tracing::warn!("Failed to retire undelivered DM group: {}", e);
+    }
+}
+
+/// Name for a new DM group, so it can be told apart (and looked up) by peer.
+fn dm_group_name(peer: &PublicKey) -> String {
+    let npub = peer.to_bech32().unwrap_or_else(|_| peer.to_hex());
+    format!("dm:{}", &npub[..npub.len().min(DM_NAME_NPUB_CHARS)])
+}
@@ -1,79 +1,121 @@
-use anyhow::{Context, Result};
-use mdk_sqlite_storage::MdkSqliteStorage;
-use nostr_sdk::ToBech32;
+use anyhow::{bail, Context, Result};
+use nostr_sdk::{Keys, ToBech32};
 use serde::Serialize;
 use std::path::PathBuf;
 
 use crate::config::Config;
-use crate::mdk_helper::{generate_keys, parse_secret_key, save_keys};
+use crate::mdk_helper::{generate_keys, is_encrypted_key, parse_secret_key, read_key_file, save_keys};
 use crate::output::print_json;
+use crate::passphrase::{read_new_passphrase, PASSPHRASE_ENV};
+use crate::signer::load_identity;
+use crate::storage::{open_storage, DbEncryption};
 
 #[derive(Serialize)]
 struct InitOutput {
     pubkey: String,
     npub: String,
-    key_file: String,
+    key_file: Option<String>,
     db_path: String,
+    db_encryption: DbEncryption,
     key_created: bool,
+    key_encrypted: bool,
     db_created: bool,
 }
 
-pub async fn run(config: &Config, nsec_file: Option<String>) -> Result<()> {
-    let default_key_path = config
-        .db_path
-        .parent()
-        .map(|p| p.join("identity.key"))
-        .unwrap_or_else(|| PathBuf::from("identity.key"));
-
-    let key_path = config.key_file.clone().unwrap_or(default_key_path);
+/// A locally stored identity key created or loaded by `init`.
+struct LocalKey {
+    keys: Keys,
+    path: PathBuf,
+    created: bool,
+    encrypted: bool,
+}
 
-    let (keys, key_created) = if let Some(nsec_path) = nsec_file {
-        let content = std::fs::read_to_string(&nsec_path)
-            .with_context(|| format!("Failed to read nsec file: {}", nsec_path))?;
-        let secret = parse_secret_key(content.trim())?;
-        let keys = nostr_sdk::Keys::new(secret);
-        save_keys(&keys, &key_path)?;
-        (keys, true)
-    } else if key_path.exists() {
-        let content = std::fs::read_to_string(&key_path)
-            .with_context(|| format!("Failed to read existing key file: {:?}", key_path))?;
-        let secret = parse_secret_key(content.trim())?;
-        (nostr_sdk::Keys::new(secret), false)
+pub async fn run(config: &Config, nsec_file: Option<String>, encrypt: bool) -> Result<()> {
+    let local_key = if config.signer.is_some() {
+        if nsec_file.is_some() || encrypt {
+            bail!("--nsec-file and --encrypt do not apply when a remote signer is configured");
+        }
+        None
     } else {
-        let keys = generate_keys();
-        save_keys(&keys, &key_path)?;
-        (keys, true)
+        Some(init_local_key(config, nsec_file, encrypt)?)
+    };
+
+    let pubkey = match &local_key {
+        Some(local) => local.keys.public_key(),
+        None => load_identity(config).await?.pubkey,
     };
 
     let db_created = if !config.db_path.exists() {
         if let Some(parent) = config.db_path.parent() {
             std::fs::create_dir_all(parent).context("Failed to create database directory")?;
         }
-        let _storage = MdkSqliteStorage::new_unencrypted(&config.db_path)
-            .context("Failed to initialize MDK database")?;
+        let _storage = open_storage(config, local_key.as_ref().map(|l| &l.keys))?;
         true
     } else {
         false
     };
 
-    let save_config = Config {
-        key_file: Some(key_path.clone()),
-        db_path: config.db_path.clone(),
-        relays: config.relays.clone(),
-    };
-    if let Err(e) = save_config.save() {
+    if let Err(e) = config.save_init_paths(local_key.as_ref().map(|l| l.path.as_path())) {
         tracing::warn!("Failed to save config: {}", e);
     }
 
     let output = InitOutput {
-        pubkey: keys.public_key().to_hex(),
-        npub: keys.public_key().to_bech32().unwrap_or_default(),
-        key_file: key_path.to_string_lossy().to_string(),
+        pubkey: pubkey.to_hex(),
+        npub: pubkey.to_bech32().unwrap_or_default(),
+        key_file: local_key.as_ref().map(|l| l.path.to_string_lossy().to_string()),
         db_path: config.db_path.to_string_lossy().to_string(),
-        key_created,
+        db_encryption: config.db_encryption,
+        key_created: local_key.as_ref().is_some_and(|l| l.created),
+        key_encrypted: local_key.as_ref().is_some_and(|l| l.encrypted),
         db_created,
     };
 
     print_json(output);
     Ok(())
 }
+
+fn init_local_key(config: &Config, nsec_file: Option<String>, encrypt: bool) -> Result<LocalKey> {
+    let default_key_path = config
+        .db_path
+        .parent()
+        .map(|p| p.join("identity.key"))
+        .unwrap_or_else(|| PathBuf::from("identity.key"));
+
+    let key_path = config.key_file.clone().unwrap_or(default_key_path);
+
+    let passphrase = if encrypt && (nsec_file.is_some() || !key_path.exists()) {
+        Some(read_new_passphrase(PASSPHRASE_ENV, config.passphrase_file.as_deref())?)
+    } else {
+        None
+    };
+
+    let (keys, key_created) = if let Some(nsec_path) = nsec_file {
+        let content = std::fs::read_to_string(&nsec_path)
+            .with_context(|| format!("Failed to read nsec file: {}", nsec_path))?;
+        let secret = parse_secret_key(content.trim())?;
+        let keys = Keys::new(secret);
+        save_keys(&keys, &key_path, passphrase.as_deref())?;
+        (keys, true)
+    } else if key_path.exists() {
+        let secret = read_key_file(config, &key_path)?;
+        (Keys::new(secret), false)
+    } else {
+        let keys = generate_keys();
+        save_keys(&keys, &key_path, passphrase.as_deref())?;
+        (keys, true)
+    };
+
+    let key_encrypted = std::fs::read_to_string(&key_path)
+        .map(|content| is_encrypted_key(content.trim()))
+        .unwrap_or(false);
+    if encrypt && !key_encrypted {
+        tracing::warn!("Existing key file is not encrypted; run 'key encrypt' to encrypt it");
+    }
+
+    Ok(LocalKey {
+        keys,
+        path: key_path,
+        created: key_created,
+        encrypted: key_encrypted,
+    })
+}
@@ -0,0 +1,86 @@
+use anyhow::{bail, Context, Result};
+use nostr_sdk::Keys;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::mdk_helper::{decrypt_secret_key, is_encrypted_key, parse_secret_key, read_key_file, save_keys};
+use crate::output::print_json;
+use crate::passphrase::{read_new_passphrase, read_passphrase, NEW_PASSPHRASE_ENV, PASSPHRASE_ENV};
+
+#[derive(Serialize)]
+struct KeyOutput {
+    key_file: String,
+    pubkey: String,
+    encrypted: bool,
+}
+
+fn key_path(config: &Config) -> Result<&PathBuf> {
+    config
+        .key_file
+        .as_ref()
+        .context("No key file specified. Run 'mdk init' first or use --key-file")
+}
+
+fn read_key_content(path: &Path) -> Result<String> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read key file: {:?}", path))?;
+    Ok(content.trim().to_string())
+}
+
+fn print_key(path: &Path, keys: &Keys, encrypted: bool) {
+    print_json(KeyOutput {
+        key_file: path.to_string_lossy().to_string(),
+        pubkey: keys.public_key().to_hex(),
+        encrypted,
+    });
+}
+
+pub async fn encrypt(config: &Config) -> Result<()> {
+    let path = key_path(config)?;
+    let content = read_key_content(path)?;
+    if is_encrypted_key(&content) {
+        bail!("Key file is already encrypted; use 'key change-passphrase'");
+    }
+
+    let keys = Keys::new(parse_secret_key(&content)?);
+    let passphrase = read_new_passphrase(PASSPHRASE_ENV, config.passphrase_file.as_deref())?;
+    save_keys(&keys, path, Some(&passphrase))?;
+
+    print_key(path, &keys, true);
+    Ok(())
+}
+
+pub async fn decrypt(config: &Config) -> Result<()> {
+    let path = key_path(config)?;
+    if !is_encrypted_key(&read_key_content(path)?) {
+        bail!("Key file is not encrypted");
+    }
+
+    let keys = Keys::new(read_key_file(config, path)?);
+    save_keys(&keys, path, None)?;
+
+    print_key(path, &keys, false);
+    Ok(())
+}
+
+pub async fn change_passphrase(config: &Config, new_passphrase_file: Option<&str>) -> Result<()> {
+    let path = key_path(config)?;
+    let content = read_key_content(path)?;
+    if !is_encrypted_key(&content) {
+        bail!("Key file is not encrypted; use 'key encrypt'");
+    }
+
+    let current = read_passphrase(config, "Current key passphrase: ")?;
+    let keys = Keys::new(decrypt_secret_key(&content, &current)?);
+
+    let new_passphrase =
+        read_new_passphrase(NEW_PASSPHRASE_ENV, new_passphrase_file.map(Path::new))?;
+    if new_passphrase == current {
+        bail!("New passphrase is the same as the current one");
+    }
+    save_keys(&keys, path, Some(&new_passphrase))?;
+
+    print_key(path, &keys, true);
+    Ok(())
+}
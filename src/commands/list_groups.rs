@@ -1,7 +1,9 @@
 use anyhow::{Context, Result};
+use mdk_storage_traits::groups::types::GroupState;
 use serde::Serialize;
 
 use crate::config::Config;
+use crate::groups;
 use crate::mdk_helper::MdkContext;
 use crate::output::print_json;
 
@@ -9,6 +11,7 @@ use crate::output::print_json;
 struct GroupInfo {
     nostr_group_id: String,
     name: String,
+    aliases: Vec<String>,
 }
 
 #[derive(Serialize)]
@@ -18,7 +21,7 @@ struct ListGroupsOutput {
 }
 
 pub async fn run(config: &Config) -> Result<()> {
-    let ctx = MdkContext::load(config)?;
+    let ctx = MdkContext::load(config).await?;
 
     let groups = ctx
         .mdk
@@ -27,9 +30,17 @@ pub async fn run(config: &Config) -> Result<()> {
 
     let group_infos: Vec<GroupInfo> = groups
         .into_iter()
-        .map(|g| GroupInfo {
-            nostr_group_id: hex::encode(g.nostr_group_id),
-            name: g.name,
+        .filter(|g| g.state != GroupState::Inactive)
+        .map(|g| {
+            let nostr_group_id = hex::encode(g.nostr_group_id);
+            GroupInfo {
+                aliases: groups::aliases_for(&config.aliases, &nostr_group_id)
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                nostr_group_id,
+                name: g.name,
+            }
         })
         .collect();
 
@@ -1,5 +1,5 @@
 use anyhow::{Context, Result};
-use nostr_sdk::nips::nip59;
+use futures::future::join_all;
 use nostr_sdk::prelude::*;
 use nostr_sdk::ToBech32;
 use serde::Serialize;
@@ -7,12 +7,17 @@ use std::time::Duration;
 
 use crate::config::Config;
 use crate::mdk_helper::MdkContext;
-use crate::nostr_client::NostrClient;
+use crate::nostr_client::{RelayPool, UnreachableRelay};
+use crate::outbox::extra_advertised_inbox_relays;
 use crate::output::print_json;
+use crate::relay_auth::AuthFailure;
+use crate::relays::RelayRole;
+use crate::signer;
 
 const KIND_WELCOME: u16 = 444;
 const KIND_GIFT_WRAP: u16 = 1059;
 const FETCH_TIMEOUT_SECS: u64 = 10;
+const FETCH_PAGE_SIZE: usize = 100;
 
 #[derive(Serialize)]
 struct WelcomeInfo {
@@ -27,35 +32,68 @@ struct WelcomeInfo {
 struct ListWelcomesOutput {
     welcomes: Vec<WelcomeInfo>,
     count: usize,
+    unreachable_relays: Vec<UnreachableRelay>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    auth_failures: Vec<AuthFailure>,
 }
 
-pub async fn run(config: &Config) -> Result<()> {
-    let ctx = MdkContext::load(config)?;
-    let nostr = NostrClient::new(&ctx.keys, config.relays.clone()).await?;
+pub async fn run(config: &Config, pool: &mut RelayPool) -> Result<()> {
+    let ctx = MdkContext::load(config).await?;
+    let nostr = pool.client(
+        ctx.signer(),
+        config.relays_with(&[RelayRole::Inbox, RelayRole::KeyPackages]),
+        config.connect_options()?,
+    );
+    let timeout = Duration::from_secs(FETCH_TIMEOUT_SECS);
 
     let pubkey = ctx.pubkey();
 
+    // Gift-wrapped welcomes share kind 1059 with DMs, so the inbox gift-wraps
+    // are paged in full rather than limited; otherwise a busy DM inbox would
+    // crowd them out. Each fetch ends once every relay has sent EOSE, with
+    // the timeout only as an upper bound.
     let welcome_filter = Filter::new()
         .kind(Kind::Custom(KIND_WELCOME))
         .pubkey(pubkey)
         .limit(50);
-
     let gift_wrap_filter = Filter::new()
         .kind(Kind::Custom(KIND_GIFT_WRAP))
         .pubkey(pubkey)
         .limit(50);
 
-    let welcome_events = nostr
-        .fetch_events(welcome_filter, Duration::from_secs(FETCH_TIMEOUT_SECS))
-        .await
-        .context("Failed to fetch welcome events")?;
-
-    let gift_wrap_events = nostr
-        .fetch_events(gift_wrap_filter, Duration::from_secs(FETCH_TIMEOUT_SECS))
-        .await
-        .context("Failed to fetch gift-wrap events")?;
+    let extra_fetch = async {
+        let extra_inbox =
+            extra_advertised_inbox_relays(&nostr, &config.relay_urls(RelayRole::Inbox), &pubkey)
+                .await;
+        nostr.fetch_events_from(&extra_inbox, gift_wrap_filter.clone(), timeout).await
+    };
+    let (welcome_events, gift_wrap_events, extra_events) = tokio::join!(
+        nostr.fetch_events(RelayRole::Inbox, welcome_filter, timeout),
+        nostr.fetch_all_events(
+            RelayRole::Inbox,
+            gift_wrap_filter.clone(),
+            FETCH_PAGE_SIZE,
+            timeout
+        ),
+        extra_fetch
+    );
+
+    let mut events = welcome_events.context("Failed to fetch welcome events")?;
+    events.extend(gift_wrap_events.context("Failed to fetch gift-wrapped welcomes")?);
+    match extra_events {
+        Ok(extra) => {
+            for event in extra {
+                if !events.iter().any(|e| e.id == event.id) {
+                    events.push(event);
+                }
+            }
+        }
+        Err(e) => tracing::warn!("Failed to fetch gift-wraps from advertised inbox relays: {}", e),
+    }
 
-    nostr.disconnect().await;
+    let (gift_wrap_events, welcome_events): (Vec<Event>, Vec<Event>) = events
+        .into_iter()
+        .partition(|event| event.kind == Kind::Custom(KIND_GIFT_WRAP));
 
     let mut welcomes: Vec<WelcomeInfo> = Vec::new();
 
@@ -69,27 +107,40 @@ pub async fn run(config: &Config) -> Result<()> {
         });
     }
 
-    for event in gift_wrap_events {
-        match nip59::extract_rumor(&ctx.keys, &event).await {
-            Ok(unwrapped) => {
-                if unwrapped.rumor.kind.as_u16() == KIND_WELCOME {
+    // One task per gift-wrap, so unwrapping with a local key (CPU-bound
+    // NIP-44 decryption) spreads over the runtime's worker threads.
+    let unwraps = gift_wrap_events.iter().map(|event| {
+        let identity = ctx.signer();
+        let event = event.clone();
+        tokio::spawn(async move { signer::extract_rumor(identity.as_ref(), &event).await })
+    });
+    let rumors = join_all(unwraps).await;
+    for (event, rumor) in gift_wrap_events.iter().zip(rumors) {
+        match rumor {
+            Ok(Ok((sender, rumor))) => {
+                if rumor.kind.as_u16() == KIND_WELCOME {
                     welcomes.push(WelcomeInfo {
                         event_id: event.id.to_hex(),
-                        from_pubkey: unwrapped.sender.to_hex(),
-                        from_npub: unwrapped.sender.to_bech32().unwrap_or_default(),
+                        from_pubkey: sender.to_hex(),
+                        from_npub: sender.to_bech32().unwrap_or_default(),
                         created_at: event.created_at.as_secs(),
                         is_gift_wrapped: true,
                     });
                 }
             }
-            Err(_) => continue,
+            Ok(Err(_)) | Err(_) => continue,
         }
     }
 
     welcomes.sort_by(|a, b| b.created_at.cmp(&a.created_at));
 
     let count = welcomes.len();
-    let output = ListWelcomesOutput { welcomes, count };
+    let output = ListWelcomesOutput {
+        welcomes,
+        count,
+        unreachable_relays: nostr.unreachable(),
+        auth_failures: nostr.auth_failures(),
+    };
 
     print_json(output);
     Ok(())
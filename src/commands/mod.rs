@@ -1,8 +1,16 @@
 pub mod init;
 pub mod publish_key_package;
+pub mod publish_inbox_relays;
 pub mod list_welcomes;
 pub mod accept_welcome;
 pub mod list_groups;
 pub mod send;
 pub mod receive;
 pub mod whoami;
+pub mod alias;
+pub mod dm;
+pub mod key;
+pub mod db;
+pub mod profile;
+pub mod config;
+pub mod relays;
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::config::{Config, ProfileInfo};
+use crate::output::print_json;
+
+#[derive(Serialize)]
+struct ListProfilesOutput {
+    profiles: Vec<ProfileInfo>,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct ProfileOutput {
+    name: String,
+    is_default: bool,
+}
+
+#[derive(Serialize)]
+struct DeleteProfileOutput {
+    name: String,
+    was_default: bool,
+    purged: bool,
+    /// Key or database files configured outside the profile directory, which
+    /// `--purge` leaves in place.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    kept_files: Vec<String>,
+}
+
+pub async fn list(config_path: &Path) -> Result<()> {
+    let profiles = Config::list_profiles(config_path)?;
+    let count = profiles.len();
+    print_json(ListProfilesOutput { profiles, count });
+    Ok(())
+}
+
+pub async fn create(config_path: &Path, name: &str) -> Result<()> {
+    print_json(Config::create_profile(config_path, name)?);
+    Ok(())
+}
+
+pub async fn use_profile(config_path: &Path, name: &str) -> Result<()> {
+    Config::set_default_profile(config_path, name)?;
+    print_json(ProfileOutput {
+        name: name.to_string(),
+        is_default: true,
+    });
+    Ok(())
+}
+
+pub async fn delete(config_path: &Path, name: &str, purge: bool) -> Result<()> {
+    // Checked before the section is removed, so a refused purge changes nothing.
+    let purge_dir = if purge {
+        Some(Config::purgeable_profile_dir(name)?)
+    } else {
+        None
+    };
+    let profile = Config::list_profiles(config_path)?
+        .into_iter()
+        .find(|profile| profile.name == name);
+
+    let was_default = Config::delete_profile(config_path, name)?;
+
+    let mut kept_files = Vec::new();
+    if let Some(dir) = purge_dir {
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)
+                .with_context(|| format!("Failed to remove profile directory: {:?}", dir))?;
+        }
+
+        let configured = profile.into_iter().flat_map(|profile| [profile.key_file, profile.db_path]);
+        for path in configured.flatten() {
+            if !Path::new(&path).starts_with(&dir) {
+                tracing::warn!("Not deleting {} (outside the profile directory)", path);
+                kept_files.push(path);
+            }
+        }
+    }
+
+    print_json(DeleteProfileOutput {
+        name: name.to_string(),
+        was_default,
+        purged: purge,
+        kept_files,
+    });
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::mdk_helper::MdkContext;
+use crate::nostr_client::{RelayPool, RelayPublishResult, UnreachableRelay};
+use crate::outbox::inbox_relays_event;
+use crate::output::print_json;
+use crate::relay_auth::AuthFailure;
+use crate::relays::RelayRole;
+
+#[derive(Serialize)]
+struct PublishInboxRelaysOutput {
+    event_id: String,
+    pubkey: String,
+    inbox_relays: Vec<String>,
+    accepted_count: usize,
+    relays: Vec<RelayPublishResult>,
+    unreachable_relays: Vec<UnreachableRelay>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    auth_failures: Vec<AuthFailure>,
+}
+
+/// Advertise our inbox relays (kind 10050) so others know where to send welcomes.
+pub async fn run(config: &Config, pool: &mut RelayPool, min_acks: usize) -> Result<()> {
+    let ctx = MdkContext::load(config).await?;
+
+    let inbox = config.relay_urls(RelayRole::Inbox);
+    if inbox.is_empty() {
+        bail!("No relay configured with the 'inbox' role");
+    }
+
+    let event = ctx
+        .sign(inbox_relays_event(&inbox))
+        .await
+        .context("Failed to sign inbox relay list")?;
+
+    // Publish where others look up relay lists and key packages, and to our write relays.
+    let targets = config.relays_with(&[RelayRole::KeyPackages, RelayRole::Write]);
+    let target_urls: Vec<_> = targets.iter().map(|relay| relay.url.clone()).collect();
+
+    let nostr = pool.client(ctx.signer(), targets, config.connect_options()?);
+    let result = nostr.publish_to(&target_urls, event).await?;
+
+    result.require_acks(min_acks)?;
+
+    let output = PublishInboxRelaysOutput {
+        event_id: result.event_id.to_hex(),
+        pubkey: ctx.pubkey().to_hex(),
+        inbox_relays: inbox.iter().map(|url| url.to_string()).collect(),
+        accepted_count: result.accepted_count(),
+        relays: result.relays,
+        unreachable_relays: nostr.unreachable(),
+        auth_failures: nostr.auth_failures(),
+    };
+
+    print_json(output);
+    Ok(())
+}
@@ -1,26 +1,31 @@
 use anyhow::{Context, Result};
 use nostr_sdk::prelude::*;
 use serde::Serialize;
-use std::time::Duration;
 
 use crate::config::Config;
 use crate::mdk_helper::MdkContext;
-use crate::nostr_client::NostrClient;
+use crate::nostr_client::{RelayPool, RelayPublishResult, UnreachableRelay};
 use crate::output::print_json;
+use crate::relay_auth::AuthFailure;
+use crate::relays::RelayRole;
 
 #[derive(Serialize)]
 struct PublishOutput {
     event_id: String,
     pubkey: String,
-    relays: Vec<String>,
+    accepted_count: usize,
+    relays: Vec<RelayPublishResult>,
+    unreachable_relays: Vec<UnreachableRelay>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    auth_failures: Vec<AuthFailure>,
 }
 
-pub async fn run(config: &Config) -> Result<()> {
-    let ctx = MdkContext::load(config)?;
+pub async fn run(config: &Config, pool: &mut RelayPool, min_acks: usize) -> Result<()> {
+    let ctx = MdkContext::load(config).await?;
 
     let (content, tags, _key_package_id) = ctx
         .mdk
-        .create_key_package_for_event(&ctx.pubkey(), ctx.relays.clone())
+        .create_key_package_for_event(&ctx.pubkey(), config.relay_urls(RelayRole::KeyPackages))
         .context("Failed to create MLS key package")?;
 
     let mut builder = EventBuilder::new(Kind::MlsKeyPackage, content);
@@ -28,21 +33,27 @@ pub async fn run(config: &Config) -> Result<()> {
         builder = builder.tag(tag);
     }
 
-    let event = builder
-        .sign(&ctx.keys)
+    let event = ctx
+        .sign(builder)
         .await
         .context("Failed to sign key package event")?;
 
-    let nostr = NostrClient::new(&ctx.keys, config.relays.clone()).await?;
-    let event_id = nostr.publish(event).await?;
+    let nostr = pool.client(
+        ctx.signer(),
+        config.relays_with(&[RelayRole::KeyPackages]),
+        config.connect_options()?,
+    );
+    let result = nostr.publish(RelayRole::KeyPackages, event).await?;
 
-    tokio::time::sleep(Duration::from_millis(500)).await;
-    nostr.disconnect().await;
+    result.require_acks(min_acks)?;
 
     let output = PublishOutput {
-        event_id: event_id.to_hex(),
+        event_id: result.event_id.to_hex(),
         pubkey: ctx.pubkey().to_hex(),
-        relays: config.relays.clone(),
+        accepted_count: result.accepted_count(),
+        relays: result.relays,
+        unreachable_relays: nostr.unreachable(),
+        auth_failures: nostr.auth_failures(),
     };
 
     print_json(output);
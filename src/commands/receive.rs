@@ -1,22 +1,32 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use mdk_core::messages::MessageProcessingResult;
+use mdk_storage_traits::GroupId;
+use nostr_sdk::nips::nip59::RANGE_RANDOM_TIMESTAMP_TWEAK;
 use nostr_sdk::prelude::*;
 use nostr_sdk::ToBech32;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
-use std::path::PathBuf;
 use std::time::Duration;
 
 use crate::config::Config;
+use crate::cursors::{CursorStore, LIVE_SLACK_SECS};
+use crate::groups;
 use crate::mdk_helper::MdkContext;
-use crate::nostr_client::NostrClient;
+use crate::nostr_client::{NostrClient, RelayPool, UnreachableRelay};
 use crate::output::print_json;
+use crate::relay_auth::AuthFailure;
+use crate::relays::{RelayConfig, RelayRole};
+use crate::timestamp::{parse_duration_secs, parse_rfc3339};
 
 const KIND_MLS_MESSAGE: u16 = 445;
+const KIND_WELCOME: u16 = 444;
 const FETCH_TIMEOUT_SECS: u64 = 10;
+const FETCH_PAGE_SIZE: usize = 100;
+const RECONNECT_BACKOFF_MIN_SECS: u64 = 1;
+const RECONNECT_BACKOFF_MAX_SECS: u64 = 60;
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct MessageInfo {
     event_id: String,
     from_pubkey: String,
@@ -24,6 +34,81 @@ struct MessageInfo {
     group_id: String,
     content: String,
     created_at: u64,
+    /// Authored by our identity (this device or another one).
+    is_own: bool,
+}
+
+/// Messages our identity authored, keyed by wrapper (kind 445) event ID.
+/// Locally-sent events cannot be decrypted by us, so they are served from MDK storage.
+struct OwnMessages {
+    include: bool,
+    sent: HashMap<EventId, MessageInfo>,
+}
+
+impl OwnMessages {
+    fn load(ctx: &MdkContext, include: bool) -> Result<Self> {
+        let mut own = Self {
+            include,
+            sent: HashMap::new(),
+        };
+        for group in ctx.mdk.get_groups().context("Failed to get groups")? {
+            own.load_group(ctx, &group.mls_group_id)?;
+        }
+        Ok(own)
+    }
+
+    /// Re-read one group's sent messages after an event in it could not be
+    /// decrypted, in case another invocation sent it since we loaded.
+    fn refresh(&mut self, ctx: &MdkContext, nostr_group_id: &str) -> Result<()> {
+        let groups = ctx.mdk.get_groups().context("Failed to get groups")?;
+        if let Some(group) = groups
+            .iter()
+            .find(|g| hex::encode(g.nostr_group_id) == nostr_group_id)
+        {
+            self.load_group(ctx, &group.mls_group_id)?;
+        }
+        Ok(())
+    }
+
+    fn load_group(&mut self, ctx: &MdkContext, mls_group_id: &GroupId) -> Result<()> {
+        let my_pubkey = ctx.pubkey();
+        let messages = ctx
+            .mdk
+            .get_messages(mls_group_id)
+            .context("Failed to get group messages")?;
+        for msg in messages.into_iter().filter(|m| m.pubkey == my_pubkey) {
+            self.sent.insert(
+                msg.wrapper_event_id,
+                MessageInfo {
+                    event_id: msg.wrapper_event_id.to_hex(),
+                    from_pubkey: msg.pubkey.to_hex(),
+                    from_npub: msg.pubkey.to_bech32().unwrap_or_default(),
+                    group_id: hex::encode(msg.mls_group_id.as_slice()),
+                    content: msg.content,
+                    created_at: msg.created_at.as_secs(),
+                    is_own: true,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A gift-wrapped welcome seen while watching; accept it with `accept-welcome`.
+#[derive(Serialize)]
+struct WelcomeNotice {
+    event_id: String,
+    from_pubkey: String,
+    from_npub: String,
+    created_at: u64,
+}
+
+/// One NDJSON line emitted by `receive --watch`.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WatchEvent {
+    Message(MessageInfo),
+    Welcome(WelcomeNotice),
 }
 
 #[derive(Serialize)]
@@ -31,185 +116,201 @@ struct ReceiveOutput {
     messages: Vec<MessageInfo>,
     count: usize,
     last_event_id: Option<String>,
+    /// More than `--max-events` events were waiting; the rest come with the next run.
+    truncated: bool,
+    unreachable_relays: Vec<UnreachableRelay>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    auth_failures: Vec<AuthFailure>,
 }
 
-#[derive(Serialize, Deserialize, Default)]
-struct CursorState {
-    cursors: HashMap<String, u64>,
-}
+/// Resolve a `--since` value to a timestamp. Event IDs are looked up in the
+/// local MDK store first, then on relays.
+async fn resolve_since(ctx: &MdkContext, nostr: &NostrClient, since: &str) -> Result<Timestamp> {
+    let since = since.trim();
 
-impl CursorState {
-    fn path() -> Option<PathBuf> {
-        dirs::home_dir().map(|h| h.join(".mdk").join("cursors.json"))
+    if let Ok(ts) = since.parse::<u64>() {
+        return Ok(Timestamp::from_secs(ts));
     }
 
-    fn load() -> Self {
-        Self::path()
-            .and_then(|p| std::fs::read_to_string(&p).ok())
-            .and_then(|s| serde_json::from_str(&s).ok())
-            .unwrap_or_default()
+    if let Some(secs) = parse_duration_secs(since) {
+        return Ok(Timestamp::from_secs(Timestamp::now().as_secs().saturating_sub(secs)));
     }
 
-    fn save(&self) {
-        if let Some(path) = Self::path() {
-            if let Some(parent) = path.parent() {
-                let _ = std::fs::create_dir_all(parent);
-            }
-            if let Ok(json) = serde_json::to_string_pretty(self) {
-                let _ = std::fs::write(path, json);
-            }
-        }
+    if let Ok(ts) = parse_rfc3339(since) {
+        return Ok(Timestamp::from_secs(ts));
     }
 
-    fn get_cursor(&self, group_id: &str) -> Option<u64> {
-        self.cursors.get(group_id).copied()
+    let event_id = if since.starts_with("nevent") {
+        Nip19Event::from_bech32(since).ok().map(|e| e.event_id)
+    } else if since.starts_with("note") {
+        EventId::from_bech32(since).ok()
+    } else {
+        EventId::from_hex(since).ok()
+    };
+
+    let Some(event_id) = event_id else {
+        bail!(
+            "Invalid --since value '{}': expected a unix timestamp, a duration like 2h, \
+             an RFC 3339 time, or an event ID",
+            since
+        );
+    };
+
+    if let Some(created_at) = local_event_timestamp(ctx, &event_id)? {
+        return Ok(created_at);
     }
 
-    fn update_cursor(&mut self, group_id: &str, timestamp: u64) {
-        let entry = self.cursors.entry(group_id.to_string()).or_insert(0);
-        if timestamp > *entry {
-            *entry = timestamp;
+    let events = nostr
+        .fetch_events(
+            RelayRole::Read,
+            Filter::new().id(event_id).limit(1),
+            Duration::from_secs(FETCH_TIMEOUT_SECS),
+        )
+        .await
+        .context("Failed to look up --since event")?;
+
+    events
+        .first()
+        .map(|e| e.created_at)
+        .with_context(|| format!("--since event not found locally or on relays: {}", event_id.to_hex()))
+}
+
+fn local_event_timestamp(ctx: &MdkContext, event_id: &EventId) -> Result<Option<Timestamp>> {
+    let groups = ctx.mdk.get_groups().context("Failed to get groups")?;
+    for group in &groups {
+        let messages = ctx
+            .mdk
+            .get_messages(&group.mls_group_id)
+            .context("Failed to get group messages")?;
+        if let Some(msg) = messages
+            .iter()
+            .find(|m| m.wrapper_event_id == *event_id || m.id == *event_id)
+        {
+            return Ok(Some(msg.created_at));
         }
     }
+    Ok(None)
 }
 
-fn parse_since(since: &str) -> Option<u64> {
-    if let Ok(ts) = since.parse::<u64>() {
-        return Some(ts);
-    }
-    None
+/// What `receive` fetches and how, as given on the command line.
+pub struct ReceiveOptions<'a> {
+    pub group_id: Option<&'a str>,
+    pub since: Option<&'a str>,
+    pub watch: bool,
+    pub poll_interval: u64,
+    pub max_events: usize,
+    pub include_own: bool,
 }
 
-pub async fn run(
-    config: &Config,
-    group_id: Option<&str>,
-    since: Option<&str>,
-    watch: bool,
-    poll_interval: u64,
-) -> Result<()> {
-    if watch {
-        return run_watch(config, group_id, poll_interval).await;
+pub async fn run(config: &Config, pool: &mut RelayPool, options: ReceiveOptions<'_>) -> Result<()> {
+    if options.watch {
+        return run_watch(config, pool, options).await;
     }
+    let ReceiveOptions { group_id, since, max_events, include_own, .. } = options;
 
-    let ctx = MdkContext::load(config)?;
-    let nostr = NostrClient::new(&ctx.keys, config.relays.clone()).await?;
+    let ctx = MdkContext::load(config).await?;
+    let group_ids_hex = target_group_ids(&ctx, config, group_id)?;
 
-    let groups = ctx
-        .mdk
-        .get_groups()
-        .context("Failed to get groups")?;
-
-    let group_ids_hex: Vec<String> = if let Some(gid) = group_id {
-        vec![gid.to_string()]
-    } else {
-        groups
-            .iter()
-            .map(|g| hex::encode(&g.nostr_group_id))
-            .collect()
-    };
+    let relays = groups::message_relays(&ctx, config, &group_ids_hex, RelayRole::Read)?;
+    let nostr = pool.client(ctx.signer(), relays, config.connect_options()?);
 
     if group_ids_hex.is_empty() {
         let output = ReceiveOutput {
             messages: vec![],
             count: 0,
             last_event_id: None,
+            truncated: false,
+            unreachable_relays: nostr.unreachable(),
+            auth_failures: nostr.auth_failures(),
         };
         print_json(output);
         return Ok(());
     }
 
-    let mut cursor_state = CursorState::load();
+    let mut cursor_state = CursorStore::load(&config.db_path, &ctx.pubkey())?;
+    let mut own = OwnMessages::load(&ctx, include_own)?;
 
-    let since_ts = since.and_then(parse_since);
-
-    let tag_h = SingleLetterTag::lowercase(Alphabet::H);
-    let mut filter = Filter::new()
-        .kind(Kind::Custom(KIND_MLS_MESSAGE))
-        .custom_tags(tag_h, group_ids_hex.iter().map(|s| s.as_str()))
-        .limit(100);
+    let since_ts = match since {
+        Some(s) => Some(resolve_since(&ctx, &nostr, s).await?),
+        None => None,
+    };
 
-    if let Some(ts) = since_ts {
-        filter = filter.since(Timestamp::from_secs(ts));
-    } else {
-        let min_cursor = group_ids_hex.iter()
-            .filter_map(|gid| cursor_state.get_cursor(gid))
-            .min();
-        if let Some(ts) = min_cursor {
-            filter = filter.since(Timestamp::from_secs(ts));
-        }
+    let mut filter = message_filter(&group_ids_hex);
+    if let Some(ts) = since_ts.or_else(|| cursor_since(&cursor_state, &group_ids_hex)) {
+        filter = filter.since(ts);
     }
 
-    let events = nostr
-        .fetch_events(filter, Duration::from_secs(FETCH_TIMEOUT_SECS))
+    let mut events = nostr
+        .fetch_all_events(
+            RelayRole::Read,
+            filter,
+            FETCH_PAGE_SIZE,
+            Duration::from_secs(FETCH_TIMEOUT_SECS),
+        )
         .await
         .context("Failed to fetch MLS messages")?;
 
-    nostr.disconnect().await;
-
-    let my_pubkey = ctx.pubkey();
-    let mut messages: Vec<MessageInfo> = Vec::new();
-
-    for event in &events {
-        if event.pubkey == my_pubkey {
-            continue;
-        }
+    // Process the oldest events first; cursors then stop at the last one
+    // processed and the next run continues from there.
+    let max_events = max_events.max(1);
+    let truncated = events.len() > max_events;
+    events.truncate(max_events);
 
-        match ctx.mdk.process_message(event) {
-            Ok(result) => {
-                if let MessageProcessingResult::ApplicationMessage(msg) = result {
-                    let group_id_hex = hex::encode(msg.mls_group_id.as_slice());
-                    cursor_state.update_cursor(&group_id_hex, event.created_at.as_secs());
-                    messages.push(MessageInfo {
-                        event_id: event.id.to_hex(),
-                        from_pubkey: msg.pubkey.to_hex(),
-                        from_npub: msg.pubkey.to_bech32().unwrap_or_default(),
-                        group_id: group_id_hex,
-                        content: msg.content,
-                        created_at: event.created_at.as_secs(),
-                    });
-                }
-            }
-            Err(_) => continue,
-        }
-    }
+    let mut messages: Vec<MessageInfo> = events
+        .iter()
+        .filter_map(|event| process_event(&ctx, event, &mut cursor_state, &mut own))
+        .collect();
 
-    cursor_state.save();
+    cursor_state.save()?;
 
     messages.sort_by(|a, b| a.created_at.cmp(&b.created_at));
 
     let last_event_id = messages.last().map(|m| m.event_id.clone());
     let count = messages.len();
-    let output = ReceiveOutput { messages, count, last_event_id };
+    let output = ReceiveOutput {
+        messages,
+        count,
+        last_event_id,
+        truncated,
+        unreachable_relays: nostr.unreachable(),
+        auth_failures: nostr.auth_failures(),
+    };
 
     print_json(output);
     Ok(())
 }
 
-async fn run_watch(config: &Config, group_id: Option<&str>, poll_interval: u64) -> Result<()> {
-    let ctx = MdkContext::load(config)?;
-    let nostr = NostrClient::new(&ctx.keys, config.relays.clone()).await?;
-
-    let groups = ctx
-        .mdk
-        .get_groups()
-        .context("Failed to get groups")?;
-
-    let group_ids_hex: Vec<String> = if let Some(gid) = group_id {
-        vec![gid.to_string()]
-    } else {
-        groups
-            .iter()
-            .map(|g| hex::encode(&g.nostr_group_id))
-            .collect()
-    };
+async fn run_watch(config: &Config, pool: &mut RelayPool, options: ReceiveOptions<'_>) -> Result<()> {
+    let ReceiveOptions { group_id, since, poll_interval, max_events, include_own, .. } = options;
+    let ctx = MdkContext::load(config).await?;
 
+    let mut group_ids_hex = target_group_ids(&ctx, config, group_id)?;
     if group_ids_hex.is_empty() {
         anyhow::bail!("No groups to watch. Join a group first.");
     }
 
-    let mut cursor_state = CursorState::load();
-    let my_pubkey = ctx.pubkey();
-    let stdout = std::io::stdout();
+    // Group messages come from the groups' relays, welcomes from our inbox relays.
+    let mut relays = groups::message_relays(&ctx, config, &group_ids_hex, RelayRole::Read)?;
+    relays.extend(
+        config
+            .relays_with(&[RelayRole::Inbox])
+            .into_iter()
+            .map(|relay| RelayConfig::for_role(relay.url, RelayRole::Inbox, relay.auth)),
+    );
+    let mut nostr = pool.client(ctx.signer(), relays, config.connect_options()?);
+
+    let mut state = WatchState {
+        cursors: CursorStore::load(&config.db_path, &ctx.pubkey())?,
+        own: OwnMessages::load(&ctx, include_own)?,
+        seen: HashSet::new(),
+        max_events,
+    };
+
+    // --since only sets where the first backfill starts.
+    let since_ts = match since {
+        Some(s) => Some(resolve_since(&ctx, &nostr, s).await?),
+        None => None,
+    };
 
     let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
     tokio::spawn(async move {
@@ -217,63 +318,331 @@ async fn run_watch(config: &Config, group_id: Option<&str>, poll_interval: u64)
         let _ = shutdown_tx.send(true);
     });
 
+    // Welcomes sent before the watch started are left to `list-welcomes`.
+    let watch_started = Timestamp::now();
+
+    let mut notifications = nostr.notifications();
+    let mut subscriptions = resync_watch(&nostr, &ctx, &group_ids_hex, since_ts, &mut state).await?;
+
+    let mut ticker = tokio::time::interval(Duration::from_secs(poll_interval.max(1)));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut backoff = Duration::from_secs(RECONNECT_BACKOFF_MIN_SECS);
+    let mut next_reconnect = tokio::time::Instant::now();
+    // Set when the subscriptions must be reopened and the gap backfilled;
+    // done on the next tick, retried with the reconnect backoff on failure.
+    let mut resync_pending = false;
+
     loop {
-        let tag_h = SingleLetterTag::lowercase(Alphabet::H);
-        let mut filter = Filter::new()
-            .kind(Kind::Custom(KIND_MLS_MESSAGE))
-            .custom_tags(tag_h, group_ids_hex.iter().map(|s| s.as_str()))
-            .limit(100);
-
-        let min_cursor = group_ids_hex.iter()
-            .filter_map(|gid| cursor_state.get_cursor(gid))
-            .min();
-        if let Some(ts) = min_cursor {
-            filter = filter.since(Timestamp::from_secs(ts));
-        }
+        tokio::select! {
+            notification = notifications.recv() => {
+                let event = match notification {
+                    Ok(RelayPoolNotification::Event { subscription_id, event, .. }) => {
+                        if !subscriptions.contains(&subscription_id) {
+                            continue;
+                        }
+                        event
+                    }
+                    Ok(RelayPoolNotification::Shutdown) => break,
+                    Ok(_) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Dropped {} relay notifications, resyncing", skipped);
+                        resync_pending = true;
+                        continue;
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                };
 
-        if let Ok(events) = nostr
-            .fetch_events(filter, Duration::from_secs(FETCH_TIMEOUT_SECS))
-            .await
-        {
-            for event in &events {
-                if event.pubkey == my_pubkey {
+                let line = if event.kind == Kind::GiftWrap {
+                    if !state.seen.insert(event.id) {
+                        continue;
+                    }
+                    welcome_notice(&ctx, &event, watch_started).await.map(WatchEvent::Welcome)
+                } else {
+                    process_live_event(&ctx, &event, &mut state).map(WatchEvent::Message)
+                };
+
+                if let Some(line) = line {
+                    emit_line(&line);
+                }
+                if event.kind != Kind::GiftWrap {
+                    if let Err(e) = state.cursors.save() {
+                        tracing::warn!("Failed to save cursors: {}", e);
+                    }
+                }
+            }
+            _ = ticker.tick() => {
+                // Groups may have been joined or left by another invocation.
+                if group_id.is_none() {
+                    match target_group_ids(&ctx, config, None) {
+                        Ok(current) if current != group_ids_hex => {
+                            tracing::info!("Group set changed, resubscribing ({} groups)", current.len());
+                            group_ids_hex = current;
+                            match groups::message_relays(&ctx, config, &group_ids_hex, RelayRole::Read) {
+                                Ok(relays) => nostr.add_relays(relays),
+                                Err(e) => tracing::warn!("Failed to look up group relays: {:#}", e),
+                            }
+                            resync_pending = true;
+                        }
+                        Ok(_) => {}
+                        Err(e) => tracing::warn!("Failed to reload groups: {:#}", e),
+                    }
+                }
+
+                if tokio::time::Instant::now() < next_reconnect {
                     continue;
                 }
 
-                match ctx.mdk.process_message(event) {
-                    Ok(result) => {
-                        if let MessageProcessingResult::ApplicationMessage(msg) = result {
-                            let group_id_hex = hex::encode(msg.mls_group_id.as_slice());
-                            cursor_state.update_cursor(&group_id_hex, event.created_at.as_secs());
-                            let info = MessageInfo {
-                                event_id: event.id.to_hex(),
-                                from_pubkey: msg.pubkey.to_hex(),
-                                from_npub: msg.pubkey.to_bech32().unwrap_or_default(),
-                                group_id: group_id_hex,
-                                content: msg.content,
-                                created_at: event.created_at.as_secs(),
-                            };
-                            if let Ok(json) = serde_json::to_string(&info) {
-                                let mut handle = stdout.lock();
-                                let _ = writeln!(handle, "{}", json);
-                                let _ = handle.flush();
-                            }
+                if nostr.connected_count().await == 0 {
+                    tracing::warn!("All relays disconnected, reconnecting");
+                    if nostr.reconnect(config.connect_timeout).await > 0 {
+                        backoff = Duration::from_secs(RECONNECT_BACKOFF_MIN_SECS);
+                        resync_pending = true;
+                    } else {
+                        tracing::warn!("Reconnect failed, retrying in {}s", backoff.as_secs());
+                        next_reconnect = tokio::time::Instant::now() + backoff;
+                        backoff = (backoff * 2).min(Duration::from_secs(RECONNECT_BACKOFF_MAX_SECS));
+                        continue;
+                    }
+                }
+
+                if resync_pending {
+                    for id in &subscriptions {
+                        nostr.unsubscribe(id).await;
+                    }
+                    subscriptions.clear();
+                    match resync_watch(&nostr, &ctx, &group_ids_hex, None, &mut state).await {
+                        Ok(ids) => {
+                            subscriptions = ids;
+                            resync_pending = false;
+                            backoff = Duration::from_secs(RECONNECT_BACKOFF_MIN_SECS);
+                        }
+                        Err(e) => {
+                            tracing::warn!("Resync failed, retrying in {}s: {:#}", backoff.as_secs(), e);
+                            next_reconnect = tokio::time::Instant::now() + backoff;
+                            backoff = (backoff * 2).min(Duration::from_secs(RECONNECT_BACKOFF_MAX_SECS));
                         }
                     }
-                    Err(_) => continue,
                 }
             }
-            cursor_state.save();
-        }
-
-        tokio::select! {
-            _ = tokio::time::sleep(Duration::from_secs(poll_interval)) => {},
             _ = shutdown_rx.changed() => {
                 break;
             }
         }
     }
 
-    nostr.disconnect().await;
     Ok(())
 }
+
+/// Receive state carried through a watch session's resubscriptions.
+struct WatchState {
+    cursors: CursorStore,
+    own: OwnMessages,
+    /// Events handled this session; streamed events are deduplicated by ID.
+    seen: HashSet<EventId>,
+    max_events: usize,
+}
+
+/// Catch up on messages since `since` (else the cursor), then open the long-lived watch
+/// subscriptions: group messages (kind 445) and gift-wraps addressed to us (kind 1059).
+async fn resync_watch(
+    nostr: &NostrClient,
+    ctx: &MdkContext,
+    group_ids_hex: &[String],
+    since: Option<Timestamp>,
+    state: &mut WatchState,
+) -> Result<Vec<SubscriptionId>> {
+    let started_at = Timestamp::now();
+
+    if let Some(since) = since.or_else(|| cursor_since(&state.cursors, group_ids_hex)) {
+        let events = nostr
+            .fetch_all_events(
+                RelayRole::Read,
+                message_filter(group_ids_hex).since(since),
+                FETCH_PAGE_SIZE,
+                Duration::from_secs(FETCH_TIMEOUT_SECS),
+            )
+            .await
+            .context("Failed to backfill MLS messages")?;
+
+        // The live subscription only covers new events, so the whole backlog
+        // is processed here, oldest first, saving cursors after each batch.
+        for batch in events.chunks(state.max_events.max(1)) {
+            for event in batch {
+                // Already streamed before a reconnect or group change.
+                if !state.seen.insert(event.id) {
+                    continue;
+                }
+                if let Some(info) = process_event(ctx, event, &mut state.cursors, &mut state.own) {
+                    emit_line(&WatchEvent::Message(info));
+                }
+            }
+            state.cursors.save()?;
+        }
+    }
+
+    // Gift-wrap timestamps are backdated by up to two days (NIP-59), so the
+    // window reaches back that far; duplicates are dropped by event ID.
+    let gift_wrap_filter = Filter::new()
+        .kind(Kind::GiftWrap)
+        .pubkey(ctx.pubkey())
+        .since(Timestamp::from_secs(
+            started_at.as_secs().saturating_sub(RANGE_RANDOM_TIMESTAMP_TWEAK.end),
+        ));
+
+    // Events stamped before `started_at` can still reach the relays after the
+    // backfill (clock skew, delayed publishes), so the subscription overlaps
+    // it; events the backfill already handled are dropped by ID.
+    let live_since = Timestamp::from_secs(started_at.as_secs().saturating_sub(LIVE_SLACK_SECS));
+    let messages = nostr
+        .subscribe(RelayRole::Read, message_filter(group_ids_hex).since(live_since))
+        .await
+        .context("Failed to subscribe to MLS messages")?;
+    let gift_wraps = nostr
+        .subscribe(RelayRole::Inbox, gift_wrap_filter)
+        .await
+        .context("Failed to subscribe to gift-wraps")?;
+
+    Ok(vec![messages, gift_wraps])
+}
+
+fn emit_line(line: &WatchEvent) {
+    if let Ok(json) = serde_json::to_string(line) {
+        let mut handle = std::io::stdout().lock();
+        let _ = writeln!(handle, "{}", json);
+        let _ = handle.flush();
+    }
+}
+
+fn message_filter(group_ids_hex: &[String]) -> Filter {
+    let tag_h = SingleLetterTag::lowercase(Alphabet::H);
+    Filter::new()
+        .kind(Kind::Custom(KIND_MLS_MESSAGE))
+        .custom_tags(tag_h, group_ids_hex.iter().map(|s| s.as_str()))
+}
+
+fn cursor_since(cursor_state: &CursorStore, group_ids_hex: &[String]) -> Option<Timestamp> {
+    group_ids_hex
+        .iter()
+        .filter_map(|gid| cursor_state.since(gid))
+        .min()
+}
+
+/// The nostr group ID (hex) carried in a kind 445 event's `h` tag.
+fn event_group_id(event: &Event) -> Option<String> {
+    let tag_h = SingleLetterTag::lowercase(Alphabet::H);
+    event
+        .tags
+        .iter()
+        .find(|t| t.single_letter_tag() == Some(tag_h))
+        .and_then(|t| t.content())
+        .map(|s| s.to_string())
+}
+
+fn target_group_ids(ctx: &MdkContext, config: &Config, group_id: Option<&str>) -> Result<Vec<String>> {
+    if let Some(gid) = group_id {
+        let group = groups::lookup(ctx, config, gid)?;
+        return Ok(vec![hex::encode(group.nostr_group_id)]);
+    }
+
+    let groups = ctx
+        .mdk
+        .get_groups()
+        .context("Failed to get groups")?;
+
+    let mut ids: Vec<String> = groups
+        .iter()
+        .map(|g| hex::encode(g.nostr_group_id))
+        .collect();
+    ids.sort();
+    Ok(ids)
+}
+
+/// Handle a fetched event. Fetches are processed oldest first, so anything
+/// behind the group's cursor has been handled already.
+fn process_event(
+    ctx: &MdkContext,
+    event: &Event,
+    cursor_state: &mut CursorStore,
+    own: &mut OwnMessages,
+) -> Option<MessageInfo> {
+    let nostr_group_id = event_group_id(event)?;
+    if cursor_state.is_seen(&nostr_group_id, event) {
+        return None;
+    }
+    cursor_state.advance(&nostr_group_id, event);
+    decrypt_event(ctx, event, &nostr_group_id, own)
+}
+
+/// Handle an event from the live subscription. These arrive in delivery
+/// order, so they are deduplicated by ID rather than against the cursor.
+fn process_live_event(
+    ctx: &MdkContext,
+    event: &Event,
+    state: &mut WatchState,
+) -> Option<MessageInfo> {
+    let nostr_group_id = event_group_id(event)?;
+    if !state.seen.insert(event.id) {
+        return None;
+    }
+    state.cursors.advance_live(&nostr_group_id, event);
+    decrypt_event(ctx, event, &nostr_group_id, &mut state.own)
+}
+
+fn decrypt_event(
+    ctx: &MdkContext,
+    event: &Event,
+    nostr_group_id: &str,
+    own: &mut OwnMessages,
+) -> Option<MessageInfo> {
+    // kind 445 events are signed with ephemeral keys, so ownership is decided
+    // by local send records and the decrypted rumor's author.
+    if let Some(info) = own.sent.get(&event.id) {
+        return own.include.then(|| info.clone());
+    }
+
+    match ctx.mdk.process_message(event) {
+        Ok(MessageProcessingResult::ApplicationMessage(msg)) => {
+            let is_own = msg.pubkey == ctx.pubkey();
+            if is_own && !own.include {
+                return None;
+            }
+            Some(MessageInfo {
+                event_id: event.id.to_hex(),
+                from_pubkey: msg.pubkey.to_hex(),
+                from_npub: msg.pubkey.to_bech32().unwrap_or_default(),
+                group_id: hex::encode(msg.mls_group_id.as_slice()),
+                content: msg.content,
+                created_at: event.created_at.as_secs(),
+                is_own,
+            })
+        }
+        Ok(_) => None,
+        Err(_) => {
+            if let Err(e) = own.refresh(ctx, nostr_group_id) {
+                tracing::warn!("Failed to reload sent messages: {:#}", e);
+            }
+            let info = own.sent.get(&event.id)?;
+            own.include.then(|| info.clone())
+        }
+    }
+}
+
+/// The welcome inside a gift-wrap, if it was sent at or after `not_before`.
+/// The rumor's `created_at` is the real send time; the wrap's is randomised.
+async fn welcome_notice(
+    ctx: &MdkContext,
+    event: &Event,
+    not_before: Timestamp,
+) -> Option<WelcomeNotice> {
+    let (sender, rumor) = ctx.extract_rumor(event).await.ok()?;
+    if rumor.kind.as_u16() != KIND_WELCOME || rumor.created_at < not_before {
+        return None;
+    }
+
+    Some(WelcomeNotice {
+        event_id: event.id.to_hex(),
+        from_pubkey: sender.to_hex(),
+        from_npub: sender.to_bech32().unwrap_or_default(),
+        created_at: event.created_at.as_secs(),
+    })
+}
@@ -0,0 +1,313 @@
+use anyhow::{Context, Result};
+use futures::future::join_all;
+use mdk_core::prelude::*;
+use mdk_sqlite_storage::MdkSqliteStorage;
+use nostr_sdk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::nostr_client::{ConnectOptions, NostrClient};
+use crate::output::print_json;
+use crate::relays::{RelayConfig, RelayRole};
+use crate::signer::load_identity;
+
+/// Kinds Marmot needs relays to carry: key packages, group messages and gift-wraps (which carry welcomes).
+const MARMOT_KINDS: [u16; 3] = [443, 445, 1059];
+
+/// Test events expire (NIP-40) shortly after being published.
+const TEST_EVENT_TTL_SECS: u64 = 60;
+
+#[derive(Serialize)]
+struct RelaysCheckOutput {
+    count: usize,
+    healthy_count: usize,
+    write_test: bool,
+    relays: Vec<RelayCheck>,
+}
+
+#[derive(Serialize)]
+struct RelayCheck {
+    url: String,
+    roles: Vec<RelayRole>,
+    auth: bool,
+    connected: bool,
+    /// Time to open the connection, excluding any AUTH wait.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connect_ms: Option<u64>,
+    /// Time spent after connecting waiting for the relay's AUTH challenge (auth relays only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_ms: Option<u64>,
+    /// Time from REQ to EOSE for a small query.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    roundtrip_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nip11: Option<RelayInformation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nip11_error: Option<String>,
+    kinds: Vec<KindCheck>,
+}
+
+impl RelayCheck {
+    fn healthy(&self) -> bool {
+        self.connected
+            && self.auth_error.is_none()
+            && self.kinds.iter().all(|kind| kind.accepted != Some(false))
+    }
+}
+
+/// The parts of a relay's NIP-11 document that matter for Marmot.
+#[derive(Serialize, Deserialize)]
+struct RelayInformation {
+    name: Option<String>,
+    software: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    supported_nips: Vec<u16>,
+    limitation: Option<Limitation>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Limitation {
+    auth_required: Option<bool>,
+    payment_required: Option<bool>,
+    restricted_writes: Option<bool>,
+    max_message_length: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct KindCheck {
+    kind: u16,
+    /// Whether the relay already serves events of this kind (dry run).
+    seen: bool,
+    /// Whether the relay accepted a test event (only with --write-test).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accepted: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+/// Connect to each configured relay and report its latency, NIP-11 information
+/// and whether it carries the kinds Marmot uses.
+pub async fn check(config: &Config, write_test: bool) -> Result<()> {
+    let identity = load_identity(config).await?;
+    let options = config.connect_options()?;
+
+    let checks = config
+        .relays
+        .iter()
+        .map(|relay| check_relay(identity.signer.clone(), relay.clone(), options, write_test));
+    let relays = join_all(checks).await;
+
+    let output = RelaysCheckOutput {
+        count: relays.len(),
+        healthy_count: relays.iter().filter(|relay| relay.healthy()).count(),
+        write_test,
+        relays,
+    };
+
+    print_json(output);
+    Ok(())
+}
+
+async fn check_relay(
+    signer: Arc<dyn NostrSigner>,
+    relay: RelayConfig,
+    options: ConnectOptions,
+    write_test: bool,
+) -> RelayCheck {
+    let url = relay.url.clone();
+    let mut report = RelayCheck {
+        url: url.to_string(),
+        roles: relay.roles.iter().copied().collect(),
+        auth: relay.auth,
+        connected: false,
+        connect_ms: None,
+        auth_ms: None,
+        roundtrip_ms: None,
+        error: None,
+        auth_error: None,
+        nip11: None,
+        nip11_error: None,
+        kinds: Vec::new(),
+    };
+
+    match fetch_information(&url, options.proxy, options.timeout).await {
+        Ok(info) => report.nip11 = Some(info),
+        Err(e) => report.nip11_error = Some(format!("{:#}", e)),
+    }
+
+    // Each relay gets its own client so connect latency is measured in isolation.
+    let nostr = NostrClient::new(signer, vec![relay], options);
+    if let Err(e) = nostr.connect().await {
+        report.error = Some(format!("{:#}", e));
+        return report;
+    }
+    report.connected = true;
+    if let Some(timing) = nostr.connect_timing(&url) {
+        report.connect_ms = Some(timing.connect.as_millis() as u64);
+        if report.auth {
+            report.auth_ms = Some(timing.auth.as_millis() as u64);
+        }
+    }
+    report.auth_error = nostr
+        .auth_failures()
+        .into_iter()
+        .next()
+        .map(|failure| failure.error);
+
+    let urls = [url];
+    for kind in MARMOT_KINDS {
+        let filter = Filter::new().kind(Kind::Custom(kind)).limit(1);
+        let start = Instant::now();
+        let seen = match nostr
+            .fetch_events_from(&urls, filter, options.timeout)
+            .await
+        {
+            Ok(events) => {
+                report.roundtrip_ms.get_or_insert(elapsed_ms(start));
+                !events.is_empty()
+            }
+            Err(e) => {
+                report.error.get_or_insert(format!("Query failed: {:#}", e));
+                false
+            }
+        };
+
+        let (accepted, message) = if write_test {
+            match publish_test_event(&nostr, &urls, kind).await {
+                Ok((accepted, message)) => (Some(accepted), message),
+                Err(e) => (Some(false), Some(format!("{:#}", e))),
+            }
+        } else {
+            (None, None)
+        };
+
+        report.kinds.push(KindCheck {
+            kind,
+            seen,
+            accepted,
+            message,
+        });
+    }
+
+    nostr.disconnect().await;
+    report
+}
+
+/// Publish a throwaway, soon-expiring event of `kind` signed by an ephemeral key.
+async fn publish_test_event(
+    nostr: &NostrClient,
+    urls: &[RelayUrl],
+    kind: u16,
+) -> Result<(bool, Option<String>)> {
+    let keys = Keys::generate();
+    let event = test_event(&keys, urls, kind).await?;
+
+    let result = nostr.publish_to(urls, event).await?;
+    let relay = result
+        .relays
+        .into_iter()
+        .next()
+        .context("Relay did not answer the test event")?;
+    Ok((relay.accepted, relay.message))
+}
+
+/// A well-formed event of `kind` (a real key package, NIP-44 content, a real
+/// gift-wrap), so relays that validate Marmot events judge it like a real one.
+async fn test_event(keys: &Keys, urls: &[RelayUrl], kind: u16) -> Result<Event> {
+    const CONTENT: &str = "marmot relay check";
+    let expiration = Tag::expiration(Timestamp::now() + Duration::from_secs(TEST_EVENT_TTL_SECS));
+
+    let builder = match kind {
+        443 => {
+            let (content, tags) = throwaway_key_package(keys, urls)?;
+            EventBuilder::new(Kind::MlsKeyPackage, content).tags(tags)
+        }
+        1059 => {
+            let rumor = EventBuilder::text_note(CONTENT).build(keys.public_key());
+            return EventBuilder::gift_wrap(keys, &keys.public_key(), rumor, [expiration])
+                .await
+                .context("Failed to build test gift-wrap");
+        }
+        _ => {
+            let content = nip44::encrypt(
+                keys.secret_key(),
+                &keys.public_key(),
+                CONTENT,
+                nip44::Version::V2,
+            )
+            .context("Failed to encrypt test event")?;
+            EventBuilder::new(Kind::Custom(kind), content)
+                .tag(Tag::custom(TagKind::h(), [keys.public_key().to_hex()]))
+        }
+    };
+
+    builder
+        .tag(expiration)
+        .sign_with_keys(keys)
+        .context("Failed to sign test event")
+}
+
+/// A key package for `keys` from a scratch MDK database, deleted afterwards,
+/// so the check never touches the real one.
+fn throwaway_key_package(keys: &Keys, urls: &[RelayUrl]) -> Result<(String, Vec<Tag>)> {
+    let path = std::env::temp_dir().join(format!(
+        "marmot-relay-check-{}.db",
+        keys.public_key().to_hex()
+    ));
+    let result = MdkSqliteStorage::new_unencrypted(&path)
+        .context("Failed to create scratch MDK database")
+        .and_then(|storage| {
+            MDK::new(storage)
+                .create_key_package_for_event(&keys.public_key(), urls.to_vec())
+                .context("Failed to create test key package")
+        })
+        .map(|(content, tags, _)| (content, tags.into_iter().collect()));
+
+    for suffix in ["", "-wal", "-shm"] {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(suffix);
+        let _ = std::fs::remove_file(name);
+    }
+    result
+}
+
+/// Fetch the relay's NIP-11 information document over HTTP(S), through the proxy if set.
+async fn fetch_information(
+    url: &RelayUrl,
+    proxy: Option<SocketAddr>,
+    timeout: Duration,
+) -> Result<RelayInformation> {
+    let http_url = url
+        .as_str()
+        .replacen("wss://", "https://", 1)
+        .replacen("ws://", "http://", 1);
+
+    let mut builder = reqwest::Client::builder().timeout(timeout);
+    if let Some(proxy) = proxy {
+        builder = builder
+            .proxy(reqwest::Proxy::all(format!("socks5h://{}", proxy)).context("Invalid proxy")?);
+    }
+    let client = builder.build().context("Failed to build HTTP client")?;
+
+    let response = client
+        .get(&http_url)
+        .header("Accept", "application/nostr+json")
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch {}", http_url))?
+        .error_for_status()?;
+
+    response.json().await.context("Invalid NIP-11 document")
+}
+
+fn elapsed_ms(start: Instant) -> u64 {
+    start.elapsed().as_millis() as u64
+}
@@ -1,60 +1,92 @@
 use anyhow::{Context, Result};
+use mdk_storage_traits::groups::types::Group;
 use nostr_sdk::prelude::*;
 use serde::Serialize;
-use std::time::Duration;
 
 use crate::config::Config;
+use crate::groups;
 use crate::mdk_helper::MdkContext;
-use crate::nostr_client::NostrClient;
+use crate::nostr_client::{
+    NostrClient, PublishResult, RelayPool, RelayPublishResult, UnreachableRelay,
+};
 use crate::output::print_json;
+use crate::relay_auth::AuthFailure;
+use crate::relays::RelayRole;
 
 #[derive(Serialize)]
 struct SendOutput {
     event_id: String,
     group_id: String,
     message_length: usize,
+    accepted_count: usize,
+    relays: Vec<RelayPublishResult>,
+    unreachable_relays: Vec<UnreachableRelay>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    auth_failures: Vec<AuthFailure>,
 }
 
-pub async fn run(config: &Config, group_id: &str, message: &str) -> Result<()> {
-    let ctx = MdkContext::load(config)?;
+pub async fn run(
+    config: &Config,
+    pool: &mut RelayPool,
+    group_id: &str,
+    message: &str,
+    min_acks: usize,
+) -> Result<()> {
+    let ctx = MdkContext::load(config).await?;
 
-    let nostr_group_id_bytes: [u8; 32] = hex::decode(group_id)
-        .context("Invalid group ID hex")?
-        .try_into()
-        .map_err(|_| anyhow::anyhow!("Group ID must be 32 bytes"))?;
+    let group = groups::lookup(&ctx, config, group_id)?;
 
-    let groups = ctx
-        .mdk
-        .get_groups()
-        .context("Failed to get groups")?;
+    let relays = groups::message_relays(
+        &ctx,
+        config,
+        &[hex::encode(group.nostr_group_id)],
+        RelayRole::Write,
+    )?;
+    let nostr = pool.client(ctx.signer(), relays, config.connect_options()?);
+    let result = publish_message(&ctx, config, &nostr, &group, message).await?;
+
+    result.require_acks(min_acks)?;
 
-    let group = groups
-        .iter()
-        .find(|g| g.nostr_group_id == nostr_group_id_bytes)
-        .with_context(|| format!("Group not found: {}", group_id))?;
+    let output = SendOutput {
+        event_id: result.event_id.to_hex(),
+        group_id: hex::encode(group.nostr_group_id),
+        message_length: message.len(),
+        accepted_count: result.accepted_count(),
+        relays: result.relays,
+        unreachable_relays: nostr.unreachable(),
+        auth_failures: nostr.auth_failures(),
+    };
 
-    let mls_group_id = group.mls_group_id.clone();
+    print_json(output);
+    Ok(())
+}
 
+/// Encrypt `message` as a kind 9 chat rumor for `group` and publish the kind 445
+/// wrapper to the group's relays.
+pub async fn publish_message(
+    ctx: &MdkContext,
+    config: &Config,
+    nostr: &NostrClient,
+    group: &Group,
+    message: &str,
+) -> Result<PublishResult> {
     let rumor = EventBuilder::new(Kind::Custom(9), message)
         .build(ctx.pubkey());
 
     let event = ctx
         .mdk
-        .create_message(&mls_group_id, rumor)
+        .create_message(&group.mls_group_id, rumor)
         .context("Failed to create MLS encrypted message")?;
 
-    let nostr = NostrClient::new(&ctx.keys, config.relays.clone()).await?;
-    let event_id = nostr.publish(event).await?;
+    let urls: Vec<RelayUrl> = groups::message_relays(
+        ctx,
+        config,
+        &[hex::encode(group.nostr_group_id)],
+        RelayRole::Write,
+    )?
+    .into_iter()
+    .map(|relay| relay.url)
+    .collect();
 
-    tokio::time::sleep(Duration::from_millis(500)).await;
-    nostr.disconnect().await;
-
-    let output = SendOutput {
-        event_id: event_id.to_hex(),
-        group_id: group_id.to_string(),
-        message_length: message.len(),
-    };
-
-    print_json(output);
-    Ok(())
+    nostr.publish_to(&urls, event).await
 }
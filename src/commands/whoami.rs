@@ -4,24 +4,27 @@ use serde::Serialize;
 use crate::config::Config;
 use crate::mdk_helper::MdkContext;
 use crate::output::print_json;
+use crate::relays::RelayConfig;
 
 #[derive(Serialize)]
 struct WhoamiOutput {
     pubkey: String,
     npub: String,
     key_file: Option<String>,
+    signer: String,
     db_path: String,
     db_exists: bool,
-    relays: Vec<String>,
+    relays: Vec<RelayConfig>,
 }
 
 pub async fn run(config: &Config) -> Result<()> {
-    let ctx = MdkContext::load(config)?;
+    let ctx = MdkContext::load(config).await?;
 
     let output = WhoamiOutput {
         pubkey: ctx.pubkey().to_hex(),
         npub: ctx.npub(),
         key_file: config.key_file.as_ref().map(|p| p.to_string_lossy().to_string()),
+        signer: if ctx.identity.is_remote() { "bunker" } else { "local" }.to_string(),
         db_path: config.db_path.to_string_lossy().to_string(),
         db_exists: config.db_path.exists(),
         relays: config.relays.clone(),
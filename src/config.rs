@@ -1,43 +1,278 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use nostr_sdk::RelayUrl;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml_edit::{DocumentMut, Item, Table, TableLike};
 
-#[derive(Serialize, Deserialize, Default)]
-struct ConfigFile {
+use crate::nostr_client::ConnectOptions;
+use crate::paths;
+use crate::relays::{self, GroupRelays, RelayConfig, RelayEntry, RelayRole};
+use crate::storage::DbEncryption;
+
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+const DEFAULT_RELAYS: &[&str] = &["wss://relay.primal.net", "wss://relay.damus.io"];
+
+/// Keys accepted at the top level and in `[profiles.<name>]` sections.
+const SETTINGS_KEYS: &[&str] = &[
+    "key_file",
+    "db_path",
+    "relays",
+    "group_relays",
+    "connect_timeout",
+    "proxy",
+    "require_proxy",
+    "passphrase_file",
+    "db_encryption",
+    "signer",
+    "aliases",
+];
+
+/// Settings that can appear at the top level of `config.toml` or in a
+/// `[profiles.<name>]` section. Profile values override top-level ones.
+#[derive(Deserialize, Default, Clone)]
+struct Settings {
     key_file: Option<String>,
     db_path: Option<String>,
-    relays: Option<Vec<String>>,
+    relays: Option<Vec<RelayEntry>>,
+    group_relays: Option<GroupRelays>,
+    connect_timeout: Option<u64>,
+    proxy: Option<String>,
+    require_proxy: Option<bool>,
+    passphrase_file: Option<String>,
+    db_encryption: Option<DbEncryption>,
+    signer: Option<String>,
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+}
+
+impl Settings {
+    fn overlay(self, over: Settings) -> Settings {
+        let mut aliases = self.aliases;
+        aliases.extend(over.aliases);
+
+        Settings {
+            key_file: over.key_file.or(self.key_file),
+            db_path: over.db_path.or(self.db_path),
+            relays: over.relays.or(self.relays),
+            group_relays: over.group_relays.or(self.group_relays),
+            connect_timeout: over.connect_timeout.or(self.connect_timeout),
+            proxy: over.proxy.or(self.proxy),
+            require_proxy: over.require_proxy.or(self.require_proxy),
+            passphrase_file: over.passphrase_file.or(self.passphrase_file),
+            db_encryption: over.db_encryption.or(self.db_encryption),
+            signer: over.signer.or(self.signer),
+            aliases,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct ConfigFile {
+    default_profile: Option<String>,
+    #[serde(flatten)]
+    settings: Settings,
+    #[serde(default)]
+    profiles: BTreeMap<String, Settings>,
+}
+
+/// The layer an effective setting came from, highest precedence first.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Cli,
+    Env,
+    Profile,
+    File,
+    Default,
+}
+
+/// An effective setting as reported by `config get` and `config list`.
+#[derive(Serialize)]
+pub struct EffectiveSetting {
+    pub key: &'static str,
+    pub value: serde_json::Value,
+    pub source: Source,
+}
+
+/// Outcome of `config validate`.
+#[derive(Serialize, Debug)]
+pub struct ValidationReport {
+    pub config_path: PathBuf,
+    pub exists: bool,
+    pub profiles: Vec<String>,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// Returned by `config validate` when the file parses but has semantic errors.
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "Config file {:?} has {} error(s): {}",
+    report.config_path,
+    report.errors.len(),
+    report.errors.join("; ")
+)]
+pub struct ConfigInvalid {
+    pub report: ValidationReport,
+}
+
+/// A profile as reported by `profile list`.
+#[derive(Serialize)]
+pub struct ProfileInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub key_file: Option<String>,
+    pub db_path: Option<String>,
 }
 
 pub struct Config {
+    /// The config file these settings were loaded from and are saved to.
+    pub config_path: PathBuf,
+    /// Active profile, if any.
+    pub profile: Option<String>,
     pub key_file: Option<PathBuf>,
     pub db_path: PathBuf,
-    pub relays: Vec<String>,
+    pub relays: Vec<RelayConfig>,
+    /// Whether group messages also go to the configured relays.
+    pub group_relays: GroupRelays,
+    pub connect_timeout: Duration,
+    /// SOCKS5 proxy (e.g. Tor) for every relay connection, as configured.
+    pub proxy: Option<String>,
+    /// Refuse to connect to relays unless a proxy is configured.
+    pub require_proxy: bool,
+    pub passphrase_file: Option<PathBuf>,
+    pub db_encryption: DbEncryption,
+    /// NIP-46 remote signer URI (`bunker://...`); the key file is unused when set.
+    pub signer: Option<String>,
+    /// Local group aliases: name -> nostr group ID (hex).
+    pub aliases: BTreeMap<String, String>,
+    /// Which layer each setting (and `profile`) was taken from.
+    pub sources: BTreeMap<&'static str, Source>,
 }
 
 impl Config {
-    fn config_file_path() -> Option<PathBuf> {
-        dirs::home_dir().map(|h| h.join(".mdk").join("config.toml"))
+    /// The config file to use: `--config`/`MDK_CONFIG`, else the XDG location
+    /// (migrating a legacy `~/.mdk` install there first).
+    pub fn resolve_path(override_path: Option<&str>) -> Result<PathBuf> {
+        if let Some(path) = override_path {
+            return Ok(PathBuf::from(path));
+        }
+
+        paths::migrate_legacy()?;
+        paths::default_config_path()
+    }
+
+    /// Directory holding a profile's key, database and cursors.
+    pub fn profile_dir(name: &str) -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join("profiles").join(name))
+    }
+
+    /// The profile directory `profile delete --purge` may remove: only for a
+    /// valid profile name, and only if it really sits directly in `profiles/`.
+    pub fn purgeable_profile_dir(name: &str) -> Result<PathBuf> {
+        validate_profile_name(name)?;
+        let root = paths::data_dir()?.join("profiles");
+        let dir = root.join(name);
+        if dir.exists() {
+            let resolved = dir
+                .canonicalize()
+                .with_context(|| format!("Failed to resolve profile directory: {:?}", dir))?;
+            let root = root
+                .canonicalize()
+                .with_context(|| format!("Failed to resolve profiles directory: {:?}", root))?;
+            if resolved.parent() != Some(root.as_path()) {
+                bail!("Profile directory {:?} is not inside {:?}; not deleting it", resolved, root);
+            }
+        }
+        Ok(dir)
+    }
+
+    /// Parse the config file, returning it along with its raw table.
+    /// A missing file is `None`; an unreadable or malformed one is an error.
+    fn read_config_file(path: &Path) -> Result<Option<(ConfigFile, toml::Table)>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read config file: {:?}", path))
+            }
+        };
+
+        let file_config: ConfigFile = toml::from_str(&content)
+            .with_context(|| format!("Invalid config file {:?}", path))?;
+        let table: toml::Table = toml::from_str(&content)
+            .with_context(|| format!("Invalid config file {:?}", path))?;
+
+        Ok(Some((file_config, table)))
     }
 
-    fn load_config_file() -> ConfigFile {
-        Self::config_file_path()
-            .and_then(|p| std::fs::read_to_string(&p).ok())
-            .and_then(|s| toml::from_str(&s).ok())
-            .unwrap_or_default()
+    fn load_config_file(path: &Path) -> Result<ConfigFile> {
+        Ok(Self::read_config_file(path)?
+            .map(|(file_config, _)| file_config)
+            .unwrap_or_default())
     }
 
-    pub fn load(cli: &crate::Cli) -> Result<Self> {
-        let file_config = Self::load_config_file();
+    pub fn load(cli: &crate::Cli, matches: &ArgMatches, config_path: PathBuf) -> Result<Self> {
+        let (mut file_config, table) = match Self::read_config_file(&config_path)? {
+            Some((file_config, table)) => {
+                for key in unknown_keys(&table) {
+                    tracing::warn!("Unknown config key '{}' in {:?}", key, config_path);
+                }
+                (file_config, table)
+            }
+            None => (ConfigFile::default(), toml::Table::new()),
+        };
+
+        let profile = cli.profile.clone().or(file_config.default_profile.clone());
+        let file_settings = match &profile {
+            Some(name) => {
+                let Some(section) = file_config.profiles.remove(name) else {
+                    bail!("Unknown profile '{}'. Create it with 'profile create {}'", name, name);
+                };
+                file_config.settings.overlay(section)
+            }
+            None => file_config.settings,
+        };
+
+        let source = |arg: Option<&str>, key: &str| -> Source {
+            match arg.and_then(|id| matches.value_source(id)) {
+                Some(ValueSource::CommandLine) => return Source::Cli,
+                Some(ValueSource::EnvVariable) => return Source::Env,
+                _ => {}
+            }
+            let in_profile = profile
+                .as_ref()
+                .and_then(|name| table.get("profiles")?.get(name)?.get(key))
+                .is_some();
+            if in_profile {
+                Source::Profile
+            } else if table.contains_key(key) {
+                Source::File
+            } else {
+                Source::Default
+            }
+        };
+        let mut sources = BTreeMap::new();
+        for key in SETTINGS_KEYS {
+            let arg = (*key != "aliases").then_some(*key);
+            sources.insert(*key, source(arg, key));
+        }
+        sources.insert("profile", source(Some("profile"), "default_profile"));
 
         let db_path = match &cli.db_path {
             Some(p) => PathBuf::from(p),
-            None => match file_config.db_path {
+            None => match file_settings.db_path {
                 Some(ref p) => PathBuf::from(p),
-                None => {
-                    let home = dirs::home_dir().context("Could not determine home directory")?;
-                    home.join(".mdk").join("state.db")
-                }
+                None => match &profile {
+                    Some(name) => Self::profile_dir(name)?.join("state.db"),
+                    None => paths::data_dir()?.join("state.db"),
+                },
             },
         };
 
@@ -47,46 +282,358 @@ impl Config {
         }
 
         let key_file = cli.key_file.as_ref().map(PathBuf::from)
-            .or_else(|| file_config.key_file.map(PathBuf::from));
+            .or_else(|| file_settings.key_file.map(PathBuf::from));
 
-        let default_relays = || vec![
-            "wss://relay.primal.net".to_string(),
-            "wss://relay.damus.io".to_string(),
-        ];
+        let relays = match (&cli.relays, &file_settings.relays) {
+            (Some(urls), _) => urls
+                .iter()
+                .map(|url| RelayConfig::parse(url))
+                .collect::<Result<Vec<_>>>()?,
+            (None, Some(entries)) => entries
+                .iter()
+                .map(RelayConfig::from_entry)
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Invalid relays in config file {:?}", config_path))?,
+            (None, None) => DEFAULT_RELAYS
+                .iter()
+                .map(|url| RelayConfig::parse(url))
+                .collect::<Result<Vec<_>>>()?,
+        };
+
+        let connect_timeout = Duration::from_secs(
+            cli.connect_timeout
+                .or(file_settings.connect_timeout)
+                .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+        );
 
-        let relays = cli.relays.clone()
-            .or(file_config.relays)
-            .unwrap_or_else(default_relays);
+        let proxy = cli.proxy.clone().or(file_settings.proxy);
+        if let Some(proxy) = &proxy {
+            parse_proxy(proxy)?;
+        }
+        let require_proxy = if cli.require_proxy {
+            true
+        } else {
+            file_settings.require_proxy.unwrap_or(false)
+        };
+
+        let passphrase_file = cli.passphrase_file.as_ref().map(PathBuf::from)
+            .or_else(|| file_settings.passphrase_file.map(PathBuf::from));
 
         Ok(Self {
+            config_path,
+            profile,
             key_file,
             db_path,
             relays,
+            group_relays: cli.group_relays
+                .or(file_settings.group_relays)
+                .unwrap_or_default(),
+            connect_timeout,
+            proxy,
+            require_proxy,
+            passphrase_file,
+            db_encryption: cli.db_encryption
+                .or(file_settings.db_encryption)
+                .unwrap_or_default(),
+            signer: cli.signer.clone().or(file_settings.signer),
+            aliases: file_settings.aliases,
+            sources,
         })
     }
 
-    pub fn save(&self) -> Result<()> {
-        let path = Self::config_file_path()
-            .context("Could not determine home directory")?;
+    /// Relays serving at least one of `roles`.
+    pub fn relays_with(&self, roles: &[RelayRole]) -> Vec<RelayConfig> {
+        relays::with_roles(&self.relays, roles)
+    }
 
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)
-                .context("Failed to create config directory")?;
+    /// How to connect to relays. Fails when a proxy is required but not configured,
+    /// so nothing connects directly.
+    pub fn connect_options(&self) -> Result<ConnectOptions> {
+        if self.require_proxy && self.proxy.is_none() {
+            bail!("require_proxy is set but no proxy is configured; refusing to connect directly");
+        }
+        Ok(ConnectOptions {
+            timeout: self.connect_timeout,
+            proxy: self.proxy.as_deref().map(parse_proxy).transpose()?,
+        })
+    }
+
+    /// Whether AUTH is enabled for `url` in the relay configuration.
+    pub fn relay_auth(&self, url: &RelayUrl) -> bool {
+        self.relays.iter().any(|relay| relay.url == *url && relay.auth)
+    }
+
+    /// URLs of the relays serving `role`.
+    pub fn relay_urls(&self, role: RelayRole) -> Vec<RelayUrl> {
+        self.relays_with(&[role]).into_iter().map(|relay| relay.url).collect()
+    }
+
+    /// Every effective setting with the layer it came from.
+    pub fn effective_settings(&self) -> Vec<EffectiveSetting> {
+        let path = |p: &Option<PathBuf>| {
+            p.as_ref().map(|p| p.to_string_lossy().to_string())
+        };
+        let values = [
+            ("profile", serde_json::json!(self.profile)),
+            ("key_file", serde_json::json!(path(&self.key_file))),
+            ("db_path", serde_json::json!(self.db_path.to_string_lossy())),
+            ("relays", serde_json::json!(self.relays)),
+            ("group_relays", serde_json::json!(self.group_relays)),
+            ("connect_timeout", serde_json::json!(self.connect_timeout.as_secs())),
+            ("proxy", serde_json::json!(self.proxy)),
+            ("require_proxy", serde_json::json!(self.require_proxy)),
+            ("passphrase_file", serde_json::json!(path(&self.passphrase_file))),
+            ("db_encryption", serde_json::json!(self.db_encryption)),
+            ("signer", serde_json::json!(self.signer)),
+            ("aliases", serde_json::json!(self.aliases)),
+        ];
+
+        values
+            .into_iter()
+            .map(|(key, value)| EffectiveSetting {
+                key,
+                value,
+                source: self.sources.get(key).copied().unwrap_or(Source::Default),
+            })
+            .collect()
+    }
+
+    /// Record where `init` put the database and the local key (if any), leaving
+    /// other settings untouched so CLI and environment overrides are not persisted.
+    pub fn save_init_paths(&self, key_file: Option<&Path>) -> Result<()> {
+        let mut doc = read_document(&self.config_path)?;
+        let section = section_mut(&mut doc, self.profile.as_deref())?;
+        if let Some(key_file) = key_file {
+            set_key(section, "key_file", Some((&*key_file.to_string_lossy()).into()));
+        }
+        set_key(section, "db_path", Some((&*self.db_path.to_string_lossy()).into()));
+        write_document(&self.config_path, &doc)
+    }
+
+    /// Set one group alias in the active profile's `[aliases]` (or the top-level
+    /// one), leaving other settings and the other layer's aliases untouched.
+    pub fn set_alias(&self, name: &str, nostr_group_id: &str) -> Result<()> {
+        let mut doc = read_document(&self.config_path)?;
+        let section = section_mut(&mut doc, self.profile.as_deref())?;
+        section
+            .entry("aliases")
+            .or_insert(Item::Table(Table::new()))
+            .as_table_like_mut()
+            .context("'aliases' in config file is not a table")?
+            .insert(name, toml_edit::value(nostr_group_id));
+        write_document(&self.config_path, &doc)
+    }
+
+    /// Remove one alias from the active profile's `[aliases]` (or the top-level
+    /// one), returning the group ID it named. Aliases that come from the other
+    /// layer are left alone, since removing them here would not unset them.
+    pub fn remove_alias(&self, name: &str) -> Result<String> {
+        let Some(nostr_group_id) = self.aliases.get(name).cloned() else {
+            bail!("No such alias: {}", name);
+        };
+
+        let mut doc = read_document(&self.config_path)?;
+        let section = section_mut(&mut doc, self.profile.as_deref())?;
+        let Some(aliases) = section.get_mut("aliases").and_then(Item::as_table_like_mut) else {
+            bail!(self.inherited_alias_error(name));
+        };
+        if aliases.remove(name).is_none() {
+            bail!(self.inherited_alias_error(name));
+        }
+        if aliases.is_empty() {
+            section.remove("aliases");
+        }
+        write_document(&self.config_path, &doc)?;
+
+        Ok(nostr_group_id)
+    }
+
+    fn inherited_alias_error(&self, name: &str) -> String {
+        match &self.profile {
+            Some(profile) => format!(
+                "Alias '{}' is set at the top level, not in profile '{}'; remove it without a profile",
+                name, profile
+            ),
+            None => format!("Alias '{}' is not set in the config file", name),
+        }
+    }
+
+    /// Record the database encryption mode in the config file, leaving other settings untouched.
+    pub fn save_db_encryption(&self, mode: DbEncryption) -> Result<()> {
+        let mut doc = read_document(&self.config_path)?;
+        let section = section_mut(&mut doc, self.profile.as_deref())?;
+        set_key(section, "db_encryption", Some(mode.as_str().into()));
+        write_document(&self.config_path, &doc)
+    }
+
+    /// Set one setting in `[profiles.<profile>]`, or at the top level when `profile` is `None`.
+    pub fn set_value(config_path: &Path, profile: Option<&str>, key: &str, raw: &str) -> Result<()> {
+        let value = parse_setting(key, raw)?;
+
+        let mut doc = read_document(config_path)?;
+        require_profile(&doc, profile)?;
+        set_key(section_mut(&mut doc, profile)?, key, Some(value));
+        write_document(config_path, &doc)
+    }
+
+    /// Remove one setting, returning whether it was present.
+    pub fn unset_value(config_path: &Path, profile: Option<&str>, key: &str) -> Result<bool> {
+        if !SETTINGS_KEYS.contains(&key) {
+            bail!("Unknown setting '{}' (known: {})", key, SETTINGS_KEYS.join(", "));
         }
 
-        let file_config = ConfigFile {
-            key_file: self.key_file.as_ref().map(|p| p.to_string_lossy().to_string()),
-            db_path: Some(self.db_path.to_string_lossy().to_string()),
-            relays: Some(self.relays.clone()),
+        let mut doc = read_document(config_path)?;
+        require_profile(&doc, profile)?;
+        let removed = section_mut(&mut doc, profile)?.remove(key).is_some();
+        if removed {
+            write_document(config_path, &doc)?;
+        }
+        Ok(removed)
+    }
+
+    pub fn list_profiles(config_path: &Path) -> Result<Vec<ProfileInfo>> {
+        let file_config = Self::load_config_file(config_path)?;
+        Ok(file_config
+            .profiles
+            .iter()
+            .map(|(name, settings)| ProfileInfo {
+                name: name.clone(),
+                is_default: file_config.default_profile.as_deref() == Some(name.as_str()),
+                key_file: settings.key_file.clone(),
+                db_path: settings.db_path.clone(),
+            })
+            .collect())
+    }
+
+    /// Add a `[profiles.<name>]` section with its own key and database paths.
+    pub fn create_profile(config_path: &Path, name: &str) -> Result<ProfileInfo> {
+        validate_profile_name(name)?;
+
+        let mut doc = read_document(config_path)?;
+        if profile_exists(&doc, name) {
+            bail!("Profile already exists: {}", name);
+        }
+
+        let dir = Self::profile_dir(name)?;
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create profile directory: {:?}", dir))?;
+
+        let info = ProfileInfo {
+            name: name.to_string(),
+            is_default: false,
+            key_file: Some(dir.join("identity.key").to_string_lossy().to_string()),
+            db_path: Some(dir.join("state.db").to_string_lossy().to_string()),
         };
 
-        let content = toml::to_string_pretty(&file_config)
-            .context("Failed to serialize config")?;
+        let section = section_mut(&mut doc, Some(name))?;
+        set_key(section, "key_file", info.key_file.as_deref().map(Into::into));
+        set_key(section, "db_path", info.db_path.as_deref().map(Into::into));
+        write_document(config_path, &doc)?;
+
+        Ok(info)
+    }
+
+    pub fn set_default_profile(config_path: &Path, name: &str) -> Result<()> {
+        let mut doc = read_document(config_path)?;
+        if !profile_exists(&doc, name) {
+            bail!("Unknown profile: {}", name);
+        }
+        doc.insert("default_profile", toml_edit::value(name));
+        write_document(config_path, &doc)
+    }
 
-        std::fs::write(&path, content)
-            .context("Failed to write config file")?;
+    /// Remove a profile's section, returning whether it was the default.
+    pub fn delete_profile(config_path: &Path, name: &str) -> Result<bool> {
+        let mut doc = read_document(config_path)?;
+        let removed = doc
+            .get_mut("profiles")
+            .and_then(Item::as_table_like_mut)
+            .and_then(|profiles| profiles.remove(name));
+        if removed.is_none() {
+            bail!("Unknown profile: {}", name);
+        }
+
+        let was_default = doc.get("default_profile").and_then(Item::as_str) == Some(name);
+        if was_default {
+            doc.remove("default_profile");
+        }
 
-        Ok(())
+        write_document(config_path, &doc)?;
+        Ok(was_default)
+    }
+
+    /// Check the config file without loading a profile: parse errors are
+    /// returned as-is, unknown keys become warnings, and bad values become errors.
+    pub fn validate(config_path: &Path) -> Result<ValidationReport> {
+        let mut report = ValidationReport {
+            config_path: config_path.to_path_buf(),
+            exists: false,
+            profiles: Vec::new(),
+            warnings: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        let Some((file_config, table)) = Self::read_config_file(config_path)? else {
+            report.warnings.push("Config file does not exist; defaults apply".to_string());
+            return Ok(report);
+        };
+        report.exists = true;
+        report.profiles = file_config.profiles.keys().cloned().collect();
+        report.warnings = unknown_keys(&table)
+            .into_iter()
+            .map(|key| format!("Unknown key '{}'", key))
+            .collect();
+
+        if let Some(name) = &file_config.default_profile {
+            if !file_config.profiles.contains_key(name) {
+                report.errors.push(format!("default_profile '{}' is not defined", name));
+            }
+        }
+
+        let sections = std::iter::once(("top level".to_string(), &file_config.settings)).chain(
+            file_config
+                .profiles
+                .iter()
+                .map(|(name, settings)| (format!("profile '{}'", name), settings)),
+        );
+        for (section, settings) in sections {
+            let parsed: Vec<RelayConfig> = settings
+                .relays
+                .iter()
+                .flatten()
+                .filter_map(|entry| match RelayConfig::from_entry(entry) {
+                    Ok(relay) => Some(relay),
+                    Err(e) => {
+                        report.errors.push(format!("{}: {:#}", section, e));
+                        None
+                    }
+                })
+                .collect();
+            if !parsed.is_empty() {
+                for role in RelayRole::ALL {
+                    if relays::with_roles(&parsed, &[role]).is_empty() {
+                        report.warnings.push(format!("{}: no relay has the '{}' role", section, role));
+                    }
+                }
+            }
+            if settings.relays.as_ref().is_some_and(|r| r.is_empty()) {
+                report.errors.push(format!("{}: relays is empty", section));
+            }
+            if let Some(proxy) = &settings.proxy {
+                if let Err(e) = parse_proxy(proxy) {
+                    report.errors.push(format!("{}: {:#}", section, e));
+                }
+            }
+            if settings.connect_timeout == Some(0) {
+                report.errors.push(format!("{}: connect_timeout must be greater than 0", section));
+            }
+        }
+
+        if !report.errors.is_empty() {
+            return Err(ConfigInvalid { report }.into());
+        }
+
+        Ok(report)
     }
 
     pub fn load_nsec(&self) -> Result<String> {
@@ -99,3 +646,193 @@ impl Config {
         Ok(content.trim().to_string())
     }
 }
+
+/// Parse the config file for in-place editing; a missing file is an empty document.
+fn read_document(path: &Path) -> Result<DocumentMut> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => content
+            .parse::<DocumentMut>()
+            .with_context(|| format!("Invalid config file {:?}", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(DocumentMut::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read config file: {:?}", path)),
+    }
+}
+
+fn write_document(path: &Path, doc: &DocumentMut) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .context("Failed to create config directory")?;
+    }
+
+    std::fs::write(path, doc.to_string())
+        .with_context(|| format!("Failed to write config file: {:?}", path))?;
+
+    Ok(())
+}
+
+fn profile_exists(doc: &DocumentMut, name: &str) -> bool {
+    doc.get("profiles")
+        .and_then(Item::as_table_like)
+        .is_some_and(|profiles| profiles.contains_key(name))
+}
+
+fn require_profile(doc: &DocumentMut, profile: Option<&str>) -> Result<()> {
+    match profile {
+        Some(name) if !profile_exists(doc, name) => {
+            bail!("Unknown profile '{}'. Create it with 'profile create {}'", name, name)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The table that settings are written to: `[profiles.<name>]`, or the top level.
+fn section_mut<'a>(doc: &'a mut DocumentMut, profile: Option<&str>) -> Result<&'a mut dyn TableLike> {
+    let Some(name) = profile else {
+        return Ok(doc.as_table_mut());
+    };
+
+    let profiles = doc.entry("profiles").or_insert_with(|| {
+        let mut table = Table::new();
+        table.set_implicit(true);
+        Item::Table(table)
+    });
+    profiles
+        .as_table_like_mut()
+        .context("'profiles' in config file is not a table")?
+        .entry(name)
+        .or_insert(Item::Table(Table::new()))
+        .as_table_like_mut()
+        .with_context(|| format!("'profiles.{}' in config file is not a table", name))
+}
+
+fn set_key(section: &mut dyn TableLike, key: &str, value: Option<toml_edit::Value>) {
+    match value {
+        Some(value) => {
+            section.insert(key, Item::Value(value));
+        }
+        None => {
+            section.remove(key);
+        }
+    }
+}
+
+/// Relays as a TOML array: bare URLs where possible, inline tables otherwise.
+fn relays_value(relays: &[RelayConfig]) -> toml_edit::Value {
+    relays
+        .iter()
+        .map(|relay| -> toml_edit::Value {
+            if relay.is_plain() {
+                return relay.url.as_str().into();
+            }
+            let mut entry = toml_edit::InlineTable::new();
+            entry.insert("url", relay.url.as_str().into());
+            if !relay.has_all_roles() {
+                entry.insert(
+                    "roles",
+                    relay.roles.iter().map(|role| role.as_str()).collect::<toml_edit::Array>().into(),
+                );
+            }
+            if relay.auth {
+                entry.insert("auth", true.into());
+            }
+            entry.into()
+        })
+        .collect::<toml_edit::Array>()
+        .into()
+}
+
+/// Convert a `config set` argument into the TOML value stored for `key`.
+fn parse_setting(key: &str, raw: &str) -> Result<toml_edit::Value> {
+    match key {
+        "key_file" | "db_path" | "passphrase_file" | "signer" => Ok(raw.into()),
+        "relays" => {
+            let relays = raw
+                .split(',')
+                .map(str::trim)
+                .filter(|r| !r.is_empty())
+                .map(RelayConfig::parse)
+                .collect::<Result<Vec<_>>>()?;
+            if relays.is_empty() {
+                bail!("relays must list at least one relay URL");
+            }
+            Ok(relays_value(&relays))
+        }
+        "connect_timeout" => {
+            let secs: u64 = raw
+                .parse()
+                .with_context(|| format!("connect_timeout must be a number of seconds: {}", raw))?;
+            if secs == 0 {
+                bail!("connect_timeout must be greater than 0");
+            }
+            Ok((secs as i64).into())
+        }
+        "proxy" => {
+            parse_proxy(raw)?;
+            Ok(raw.into())
+        }
+        "require_proxy" => {
+            let required: bool = raw
+                .parse()
+                .with_context(|| format!("require_proxy must be true or false: {}", raw))?;
+            Ok(required.into())
+        }
+        "group_relays" => {
+            let mode = <GroupRelays as clap::ValueEnum>::from_str(raw, true)
+                .map_err(|e| anyhow::anyhow!("Invalid group_relays '{}': {}", raw, e))?;
+            Ok(mode.as_str().into())
+        }
+        "db_encryption" => {
+            let mode = <DbEncryption as clap::ValueEnum>::from_str(raw, true)
+                .map_err(|e| anyhow::anyhow!("Invalid db_encryption '{}': {}", raw, e))?;
+            Ok(mode.as_str().into())
+        }
+        "aliases" => bail!("Use 'alias' and 'unalias' to manage group aliases"),
+        _ => bail!("Unknown setting '{}' (known: {})", key, SETTINGS_KEYS.join(", ")),
+    }
+}
+
+/// Parse a SOCKS5 proxy address: `socks5://host:port`, `socks5h://host:port` or `host:port`.
+fn parse_proxy(proxy: &str) -> Result<SocketAddr> {
+    let addr = proxy
+        .strip_prefix("socks5h://")
+        .or_else(|| proxy.strip_prefix("socks5://"))
+        .unwrap_or(proxy)
+        .trim_end_matches('/');
+    if addr.contains("://") {
+        bail!("Unsupported proxy scheme (only SOCKS5 is supported): {}", proxy);
+    }
+
+    addr.to_socket_addrs()
+        .with_context(|| format!("Invalid proxy address: {}", proxy))?
+        .next()
+        .with_context(|| format!("Proxy address did not resolve: {}", proxy))
+}
+
+/// Keys in the config table that no setting reads, as dotted paths.
+fn unknown_keys(table: &toml::Table) -> Vec<String> {
+    let mut unknown = Vec::new();
+    for (key, value) in table {
+        match key.as_str() {
+            "default_profile" => {}
+            "profiles" => {
+                for (name, section) in value.as_table().into_iter().flatten() {
+                    for key in section.as_table().into_iter().flat_map(|t| t.keys()) {
+                        if !SETTINGS_KEYS.contains(&key.as_str()) {
+                            unknown.push(format!("profiles.{}.{}", name, key));
+                        }
+                    }
+                }
+            }
+            key if SETTINGS_KEYS.contains(&key) => {}
+            key => unknown.push(key.to_string()),
+        }
+    }
+    unknown
+}
+
+fn validate_profile_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        bail!("Profile names may only contain letters, digits, '-' and '_': {}", name);
+    }
+    Ok(())
+}
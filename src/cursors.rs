@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use nostr_sdk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// How far the cursor trails the newest streamed event. Live events arrive in
+/// delivery order, not timestamp order, so older ones may still be on their way.
+pub const LIVE_SLACK_SECS: u64 = 300;
+
+/// Receive position within one group: the newest `created_at` seen and the
+/// IDs of the events already handled at exactly that second.
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct GroupCursor {
+    timestamp: u64,
+    seen_ids: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CursorFile {
+    /// Identity pubkey (hex) -> nostr group ID (hex) -> cursor.
+    identities: HashMap<String, HashMap<String, GroupCursor>>,
+}
+
+/// Per-identity receive cursors, stored in a file beside the MDK database.
+pub struct CursorStore {
+    path: PathBuf,
+    identity: String,
+    file: CursorFile,
+}
+
+impl CursorStore {
+    pub fn path_for(db_path: &Path) -> PathBuf {
+        let mut name = db_path
+            .file_name()
+            .map(|n| n.to_os_string())
+            .unwrap_or_else(|| "state.db".into());
+        name.push(".cursors.json");
+        db_path.with_file_name(name)
+    }
+
+    pub fn load(db_path: &Path, identity: &PublicKey) -> Result<Self> {
+        let path = Self::path_for(db_path);
+        let file = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse cursor file: {:?}", path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => CursorFile::default(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read cursor file: {:?}", path));
+            }
+        };
+
+        Ok(Self {
+            path,
+            identity: identity.to_hex(),
+            file,
+        })
+    }
+
+    fn cursors(&self) -> Option<&HashMap<String, GroupCursor>> {
+        self.file.identities.get(&self.identity)
+    }
+
+    /// Timestamp to request events from. Inclusive, so boundary events are
+    /// re-fetched and filtered with [`CursorStore::is_seen`].
+    pub fn since(&self, group_id: &str) -> Option<Timestamp> {
+        self.cursors()
+            .and_then(|c| c.get(group_id))
+            .map(|c| Timestamp::from_secs(c.timestamp))
+    }
+
+    pub fn is_seen(&self, group_id: &str, event: &Event) -> bool {
+        let Some(cursor) = self.cursors().and_then(|c| c.get(group_id)) else {
+            return false;
+        };
+        let created_at = event.created_at.as_secs();
+        created_at < cursor.timestamp
+            || (created_at == cursor.timestamp && cursor.seen_ids.contains(&event.id.to_hex()))
+    }
+
+    pub fn advance(&mut self, group_id: &str, event: &Event) {
+        let cursor = self
+            .file
+            .identities
+            .entry(self.identity.clone())
+            .or_default()
+            .entry(group_id.to_string())
+            .or_default();
+
+        let created_at = event.created_at.as_secs();
+        if created_at > cursor.timestamp {
+            cursor.timestamp = created_at;
+            cursor.seen_ids.clear();
+        }
+        if created_at == cursor.timestamp {
+            cursor.seen_ids.insert(event.id.to_hex());
+        }
+    }
+
+    /// Record a streamed event. The cursor trails it by [`LIVE_SLACK_SECS`] so
+    /// late arrivals stamped earlier are still fetched by the next backfill;
+    /// events in that window are deduplicated by ID, not by timestamp.
+    pub fn advance_live(&mut self, group_id: &str, event: &Event) {
+        let cursor = self
+            .file
+            .identities
+            .entry(self.identity.clone())
+            .or_default()
+            .entry(group_id.to_string())
+            .or_default();
+
+        let trailing = event.created_at.as_secs().saturating_sub(LIVE_SLACK_SECS);
+        if trailing > cursor.timestamp {
+            cursor.timestamp = trailing;
+            cursor.seen_ids.clear();
+        }
+    }
+
+    /// Write the cursor file atomically (temp file + rename).
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+        }
+
+        let json = serde_json::to_string_pretty(&self.file).context("Failed to serialize cursors")?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        {
+            let mut file = std::fs::File::create(&tmp_path)
+                .with_context(|| format!("Failed to create {:?}", tmp_path))?;
+            file.write_all(json.as_bytes())
+                .with_context(|| format!("Failed to write {:?}", tmp_path))?;
+            file.sync_all()
+                .with_context(|| format!("Failed to sync {:?}", tmp_path))?;
+        }
+
+        std::fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to replace cursor file: {:?}", self.path))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use mdk_storage_traits::groups::types::{Group, GroupState};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::mdk_helper::MdkContext;
+use crate::relays::{GroupRelays, RelayConfig, RelayRole};
+
+const MAX_SUGGESTIONS: usize = 5;
+
+/// A local group named in a lookup error.
+#[derive(Serialize, Clone, Debug)]
+pub struct GroupCandidate {
+    pub nostr_group_id: String,
+    pub name: String,
+}
+
+impl GroupCandidate {
+    fn from_group(group: &Group) -> Self {
+        Self {
+            nostr_group_id: hex::encode(group.nostr_group_id),
+            name: group.name.clone(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GroupLookupError {
+    #[error("Group not found: {input}{}", format_candidates("did you mean", candidates))]
+    NotFound {
+        input: String,
+        candidates: Vec<GroupCandidate>,
+    },
+    #[error("Group reference '{input}' is ambiguous{}", format_candidates("matches", candidates))]
+    Ambiguous {
+        input: String,
+        candidates: Vec<GroupCandidate>,
+    },
+}
+
+impl GroupLookupError {
+    pub fn candidates(&self) -> &[GroupCandidate] {
+        match self {
+            Self::NotFound { candidates, .. } | Self::Ambiguous { candidates, .. } => candidates,
+        }
+    }
+}
+
+fn format_candidates(label: &str, candidates: &[GroupCandidate]) -> String {
+    if candidates.is_empty() {
+        return String::new();
+    }
+    let list: Vec<String> = candidates
+        .iter()
+        .map(|c| format!("{} ({})", c.nostr_group_id, c.name))
+        .collect();
+    format!(" ({}: {})", label, list.join(", "))
+}
+
+/// Look up a local group from a user-supplied reference (see [`resolve`]).
+pub fn lookup(ctx: &MdkContext, config: &Config, input: &str) -> Result<Group> {
+    let groups = ctx.mdk.get_groups().context("Failed to get groups")?;
+    let group = resolve(&groups, &config.aliases, input)?;
+    Ok(group.clone())
+}
+
+/// Relays carrying kind 445 messages for the given groups (nostr group IDs, hex):
+/// each group's own relays, plus the configured relays serving `role` in
+/// `union` mode or when a group lists none.
+pub fn message_relays(
+    ctx: &MdkContext,
+    config: &Config,
+    group_ids_hex: &[String],
+    role: RelayRole,
+) -> Result<Vec<RelayConfig>> {
+    let groups = ctx.mdk.get_groups().context("Failed to get groups")?;
+
+    let mut relays: Vec<RelayConfig> = Vec::new();
+    let mut add = |relay: RelayConfig| {
+        if !relays.iter().any(|r| r.url == relay.url) {
+            relays.push(relay);
+        }
+    };
+
+    let mut include_configured = config.group_relays == GroupRelays::Union;
+    for group in groups
+        .iter()
+        .filter(|g| group_ids_hex.contains(&hex::encode(g.nostr_group_id)))
+    {
+        let group_relays = ctx
+            .mdk
+            .get_relays(&group.mls_group_id)
+            .context("Failed to get group relays")?;
+        if group_relays.is_empty() {
+            include_configured = true;
+        }
+        for url in group_relays {
+            let auth = config.relay_auth(&url);
+            add(RelayConfig::for_role(url, role, auth));
+        }
+    }
+
+    if include_configured {
+        for url in config.relay_urls(role) {
+            let auth = config.relay_auth(&url);
+            add(RelayConfig::for_role(url, role, auth));
+        }
+    }
+
+    Ok(relays)
+}
+
+/// Resolve a group reference against local groups. Accepts the full nostr
+/// group ID hex, a local alias, a unique hex prefix, or an exact group name.
+pub fn resolve<'a>(
+    groups: &'a [Group],
+    aliases: &BTreeMap<String, String>,
+    input: &str,
+) -> std::result::Result<&'a Group, GroupLookupError> {
+    let needle = input.trim();
+    let needle_lower = needle.to_lowercase();
+    let target = aliases
+        .get(needle)
+        .map(|id| id.to_lowercase())
+        .unwrap_or_else(|| needle_lower.clone());
+
+    if let Some(group) = groups
+        .iter()
+        .find(|g| hex::encode(g.nostr_group_id) == target)
+    {
+        return Ok(group);
+    }
+
+    let is_hex = !needle.is_empty() && needle.chars().all(|c| c.is_ascii_hexdigit());
+    let mut matches: Vec<&Group> = Vec::new();
+    if is_hex {
+        matches.extend(
+            groups
+                .iter()
+                .filter(|g| hex::encode(g.nostr_group_id).starts_with(&needle_lower)),
+        );
+    }
+    // Unnamed groups must not all match an empty input, and inactive groups
+    // (e.g. DMs whose welcome was never delivered) must not make names ambiguous.
+    for group in groups
+        .iter()
+        .filter(|g| !needle.is_empty() && g.name == needle && g.state != GroupState::Inactive)
+    {
+        if !matches.iter().any(|m| m.nostr_group_id == group.nostr_group_id) {
+            matches.push(group);
+        }
+    }
+
+    match matches.len() {
+        1 => Ok(matches[0]),
+        0 => Err(GroupLookupError::NotFound {
+            input: needle.to_string(),
+            candidates: close_matches(groups, aliases, &needle_lower),
+        }),
+        _ => Err(GroupLookupError::Ambiguous {
+            input: needle.to_string(),
+            candidates: matches.into_iter().map(GroupCandidate::from_group).collect(),
+        }),
+    }
+}
+
+/// Groups whose name, alias or ID looks like a typo of `needle`.
+fn close_matches(groups: &[Group], aliases: &BTreeMap<String, String>, needle: &str) -> Vec<GroupCandidate> {
+    let mut scored: Vec<(usize, &Group)> = groups
+        .iter()
+        .filter_map(|g| {
+            let id = hex::encode(g.nostr_group_id);
+            let id_distance = edit_distance(needle, &id[..needle.len().min(id.len())]);
+            let names = aliases
+                .iter()
+                .filter(|(_, target)| target.eq_ignore_ascii_case(&id))
+                .map(|(alias, _)| alias.to_lowercase())
+                .chain(std::iter::once(g.name.to_lowercase()))
+                .filter(|name| !name.is_empty());
+            let name_score = names
+                .map(|name| {
+                    if name.contains(needle) || needle.contains(&name) {
+                        0
+                    } else {
+                        edit_distance(needle, &name)
+                    }
+                })
+                .min()
+                .unwrap_or(usize::MAX);
+            let score = id_distance.min(name_score);
+            (score <= 2 + needle.len() / 8).then_some((score, g))
+        })
+        .collect();
+
+    scored.sort_by_key(|(score, _)| *score);
+    scored
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, g)| GroupCandidate::from_group(g))
+        .collect()
+}
+
+/// Aliases pointing at the given nostr group ID (hex).
+pub fn aliases_for<'a>(aliases: &'a BTreeMap<String, String>, nostr_group_id: &str) -> Vec<&'a str> {
+    aliases
+        .iter()
+        .filter(|(_, target)| target.eq_ignore_ascii_case(nostr_group_id))
+        .map(|(alias, _)| alias.as_str())
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
@@ -1,29 +1,81 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use nostr_client::RelayPool;
 
 mod commands;
 mod config;
+mod cursors;
+mod groups;
 mod mdk_helper;
 mod nostr_client;
+mod outbox;
 mod output;
+mod passphrase;
+mod paths;
+mod relay_auth;
+mod relays;
+mod signer;
+mod storage;
+mod timestamp;
 
 #[derive(Parser)]
 #[command(name = "mdk")]
 #[command(about = "CLI for MLS-encrypted messaging over Nostr")]
 #[command(version)]
 struct Cli {
+    /// Path to config file (default: $XDG_CONFIG_HOME/marmot/config.toml)
+    #[arg(long, env = "MDK_CONFIG")]
+    config: Option<String>,
+
+    /// Named profile to use (or set MDK_PROFILE)
+    #[arg(long, env = "MDK_PROFILE")]
+    profile: Option<String>,
+
     /// Path to nsec key file (or set MDK_KEY_FILE env var)
     #[arg(long, env = "MDK_KEY_FILE")]
     key_file: Option<String>,
 
-    /// Path to SQLite database (default: ~/.mdk/state.db)
+    /// Path to SQLite database (default: $XDG_DATA_HOME/marmot/state.db)
     #[arg(long, env = "MDK_DB_PATH")]
     db_path: Option<String>,
 
-    /// Relay URLs (comma-separated, or set MDK_RELAYS env var)
+    /// Database encryption mode (or set MDK_DB_ENCRYPTION)
+    #[arg(long, env = "MDK_DB_ENCRYPTION", value_enum)]
+    db_encryption: Option<storage::DbEncryption>,
+
+    /// Relay URLs used for every role (comma-separated, or set MDK_RELAYS env var)
     #[arg(long, env = "MDK_RELAYS", value_delimiter = ',')]
     relays: Option<Vec<String>>,
 
+    /// Relays for group messages: the group's own, or those plus configured relays
+    /// (or set MDK_GROUP_RELAYS)
+    #[arg(long, env = "MDK_GROUP_RELAYS", value_enum)]
+    group_relays: Option<relays::GroupRelays>,
+
+    /// NIP-46 remote signer URI, bunker://... (or set MDK_SIGNER)
+    #[arg(long, env = "MDK_SIGNER")]
+    signer: Option<String>,
+
+    /// File containing the key passphrase (or set MDK_KEY_PASSPHRASE)
+    #[arg(long, env = "MDK_PASSPHRASE_FILE")]
+    passphrase_file: Option<String>,
+
+    /// SOCKS5 proxy for relay connections, e.g. socks5://127.0.0.1:9050 for Tor
+    /// (or set MDK_PROXY)
+    #[arg(long, env = "MDK_PROXY")]
+    proxy: Option<String>,
+
+    /// Fail instead of connecting to relays without a proxy (or set MDK_REQUIRE_PROXY)
+    #[arg(long, env = "MDK_REQUIRE_PROXY")]
+    require_proxy: bool,
+
+    /// Seconds to wait for relays to connect (default: 10)
+    #[arg(long, env = "MDK_CONNECT_TIMEOUT")]
+    connect_timeout: Option<u64>,
+
     /// Enable verbose logging
     #[arg(short, long)]
     verbose: bool,
@@ -39,10 +91,24 @@ enum Commands {
         /// Path to nsec key file (hex format)
         #[arg(long)]
         nsec_file: Option<String>,
+        /// Store the identity key encrypted with a passphrase (NIP-49 ncryptsec)
+        #[arg(long)]
+        encrypt: bool,
     },
 
     /// Publish MLS key package to relays (kind 443)
-    PublishKeyPackage,
+    PublishKeyPackage {
+        /// Minimum number of relays that must accept the event
+        #[arg(long, default_value = "1")]
+        min_acks: usize,
+    },
+
+    /// Publish our inbox relay list (kind 10050) so others can deliver welcomes
+    PublishInboxRelays {
+        /// Minimum number of relays that must accept the event
+        #[arg(long, default_value = "1")]
+        min_acks: usize,
+    },
 
     /// List pending welcome invitations
     ListWelcomes,
@@ -58,35 +124,185 @@ enum Commands {
 
     /// Send a message to a group
     Send {
-        /// Group ID (hex)
+        /// Group: ID, unique ID prefix, name or alias
         group_id: String,
         /// Message content
         message: String,
+        /// Minimum number of relays that must accept the event
+        #[arg(long, default_value = "1")]
+        min_acks: usize,
+    },
+
+    /// Send a direct message, creating a two-member group with the peer if needed
+    Dm {
+        /// Peer public key (npub or hex)
+        peer: String,
+        /// Message content
+        message: String,
+        /// Minimum number of relays that must accept each event
+        #[arg(long, default_value = "1")]
+        min_acks: usize,
     },
 
     /// Receive and display new messages (polls relays once)
     Receive {
-        /// Group ID to receive from (optional, receives from all if omitted)
+        /// Group to receive from: ID, unique ID prefix, name or alias (all groups if omitted)
         #[arg(long)]
         group_id: Option<String>,
-        /// Fetch only events after this timestamp or event ID
+        /// Fetch only events after this point: unix timestamp, duration ago (e.g. 2h),
+        /// RFC 3339 time, or event ID (hex, note or nevent). With --watch, where the
+        /// initial backfill starts
         #[arg(long)]
         since: Option<String>,
-        /// Stream new messages continuously (NDJSON output)
+        /// Stream new messages continuously over a live subscription (NDJSON output)
         #[arg(long)]
         watch: bool,
-        /// Poll interval in seconds (used with --watch)
+        /// Seconds between group membership and relay health checks (used with --watch)
         #[arg(long, default_value = "5")]
         poll_interval: u64,
+        /// Upper bound on backlog events processed per run, oldest first; the rest
+        /// wait for the next run. The whole backlog is still fetched to find the
+        /// oldest. With --watch, nothing is skipped: the backlog is processed in
+        /// batches of this many, saving cursors after each
+        #[arg(long, default_value = "10000")]
+        max_events: usize,
+        /// Include messages authored by our own identity (this and other devices)
+        #[arg(long)]
+        include_own: bool,
     },
 
     /// Show identity info (npub, pubkey)
     Whoami,
+
+    /// Manage identity key encryption (NIP-49)
+    Key {
+        #[command(subcommand)]
+        action: KeyAction,
+    },
+
+    /// Manage database encryption (SQLCipher)
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+
+    /// Inspect the configured relays
+    Relays {
+        #[command(subcommand)]
+        action: RelaysAction,
+    },
+
+    /// Manage named profiles (separate identity, database and cursors)
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+
+    /// Inspect and edit the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Run commands read from stdin, one per line, sharing relay connections
+    Batch,
+
+    /// Set a local alias for a group
+    Alias {
+        /// Group: ID, unique ID prefix, name or existing alias
+        group_id: String,
+        /// Alias to assign
+        name: String,
+    },
+
+    /// Remove a local group alias
+    Unalias {
+        /// Alias to remove
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeyAction {
+    /// Encrypt a plaintext key file with a passphrase
+    Encrypt,
+    /// Decrypt the key file back to plaintext hex
+    Decrypt,
+    /// Re-encrypt the key file with a new passphrase
+    ChangePassphrase {
+        /// File containing the new passphrase (or set MDK_NEW_KEY_PASSPHRASE)
+        #[arg(long)]
+        new_passphrase_file: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RelaysAction {
+    /// Connect to each relay and report latency, NIP-11 info and accepted kinds
+    Check {
+        /// Also publish short-lived test events (kinds 443, 445, 1059) from a throwaway key
+        #[arg(long)]
+        write_test: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileAction {
+    /// List configured profiles
+    List,
+    /// Create a profile with its own key and database paths
+    Create {
+        name: String,
+    },
+    /// Make a profile the default
+    Use {
+        name: String,
+    },
+    /// Remove a profile from the config
+    Delete {
+        name: String,
+        /// Also delete the profile directory (key, database, cursors)
+        #[arg(long)]
+        purge: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Show a setting's effective value and where it came from
+    Get {
+        key: String,
+    },
+    /// Set a setting in the config file (in the --profile section if given)
+    Set {
+        key: String,
+        /// New value (comma-separated for relays)
+        value: String,
+    },
+    /// Remove a setting from the config file (from the --profile section if given)
+    Unset {
+        key: String,
+    },
+    /// Show all effective settings and where they came from
+    List,
+    /// Check the config file for syntax errors, unknown keys and bad values
+    Validate,
+}
+
+#[derive(Subcommand)]
+enum DbAction {
+    /// Encrypt an existing unencrypted database in place
+    Encrypt {
+        /// Where the database key comes from
+        #[arg(long, value_enum, default_value = "identity")]
+        mode: storage::DbEncryption,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     // Initialize logging
     let filter = if cli.verbose { "debug" } else { "info" };
@@ -95,24 +311,189 @@ async fn main() -> Result<()> {
         .with_target(false)
         .init();
 
+    if let Err(e) = run(cli, &matches).await {
+        report_error(&e);
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn report_error(e: &anyhow::Error) {
+    if let Some(lookup) = e.downcast_ref::<groups::GroupLookupError>() {
+        output::print_error_details(lookup, lookup.candidates());
+    } else if let Some(invalid) = e.downcast_ref::<config::ConfigInvalid>() {
+        output::print_error_details(invalid, &invalid.report);
+    } else {
+        output::print_error(format!("{:#}", e));
+    }
+}
+
+async fn run(cli: Cli, matches: &ArgMatches) -> Result<()> {
+    let config_path = config::Config::resolve_path(cli.config.as_deref())?;
+
+    // Profile management edits the config file directly and must work even
+    // when the selected profile does not exist yet.
+    if let Commands::Profile { action } = &cli.command {
+        return match action {
+            ProfileAction::List => commands::profile::list(&config_path).await,
+            ProfileAction::Create { name } => commands::profile::create(&config_path, name).await,
+            ProfileAction::Use { name } => commands::profile::use_profile(&config_path, name).await,
+            ProfileAction::Delete { name, purge } => {
+                commands::profile::delete(&config_path, name, *purge).await
+            }
+        };
+    }
+
+    // Validation must report problems that would make loading fail, and
+    // set/unset must be able to repair them.
+    if let Commands::Config { action } = &cli.command {
+        let profile = cli.profile.as_deref();
+        match action {
+            ConfigAction::Validate => return commands::config::validate(&config_path).await,
+            ConfigAction::Set { key, value } => {
+                return commands::config::set(&config_path, profile, key, value).await
+            }
+            ConfigAction::Unset { key } => {
+                return commands::config::unset(&config_path, profile, key).await
+            }
+            ConfigAction::Get { .. } | ConfigAction::List => {}
+        }
+    }
+
     // Load config
-    let config = config::Config::load(&cli)?;
+    let config = config::Config::load(&cli, matches, config_path.clone())?;
 
-    // Dispatch command
-    match cli.command {
-        Commands::Init { nsec_file } => commands::init::run(&config, nsec_file).await,
-        Commands::PublishKeyPackage => commands::publish_key_package::run(&config).await,
-        Commands::ListWelcomes => commands::list_welcomes::run(&config).await,
+    if let Commands::Batch = cli.command {
+        return run_batch(&cli, matches, &config_path, config).await;
+    }
+
+    let mut pool = RelayPool::default();
+    let result = dispatch(&config, cli.command, &mut pool).await;
+    pool.shutdown().await;
+    result
+}
+
+/// A line of `marmot batch` input: a subcommand and its arguments.
+#[derive(Parser)]
+#[command(name = "marmot", no_binary_name = true)]
+struct BatchLine {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+/// Run each stdin line as a command with the global options of the batch,
+/// reusing relay connections between them. Errors are reported per line.
+/// The config is reloaded after commands that write it, so later lines see
+/// new aliases and the database encryption mode.
+async fn run_batch(
+    cli: &Cli,
+    matches: &ArgMatches,
+    config_path: &Path,
+    mut config: config::Config,
+) -> Result<()> {
+    let mut pool = RelayPool::default();
+    let mut failed = 0;
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await.context("Failed to read stdin")? {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (result, reload) = match shlex::split(line) {
+            Some(args) => match BatchLine::try_parse_from(args) {
+                Ok(batch_line) => {
+                    let reload = writes_config(&batch_line.command);
+                    (dispatch(&config, batch_line.command, &mut pool).await, reload)
+                }
+                Err(e) => (Err(anyhow!(e.to_string().trim().to_string())), false),
+            },
+            None => (Err(anyhow!("Unbalanced quotes: {}", line)), false),
+        };
+        if let Err(e) = result {
+            report_error(&e);
+            failed += 1;
+        }
+        if reload {
+            config = config::Config::load(cli, matches, config_path.to_path_buf())
+                .context("Failed to reload config")?;
+        }
+    }
+
+    pool.shutdown().await;
+
+    if failed > 0 {
+        bail!("{} batch command(s) failed", failed);
+    }
+    Ok(())
+}
+
+/// Commands that rewrite parts of the config file the rest of a batch depends on.
+fn writes_config(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::Init { .. } | Commands::Alias { .. } | Commands::Unalias { .. } | Commands::Db { .. }
+    )
+}
+
+async fn dispatch(config: &config::Config, command: Commands, pool: &mut RelayPool) -> Result<()> {
+    match command {
+        Commands::Init { nsec_file, encrypt } => commands::init::run(config, nsec_file, encrypt).await,
+        Commands::PublishKeyPackage { min_acks } => {
+            commands::publish_key_package::run(config, pool, min_acks).await
+        }
+        Commands::PublishInboxRelays { min_acks } => {
+            commands::publish_inbox_relays::run(config, pool, min_acks).await
+        }
+        Commands::ListWelcomes => commands::list_welcomes::run(config, pool).await,
         Commands::AcceptWelcome { event_id } => {
-            commands::accept_welcome::run(&config, &event_id).await
+            commands::accept_welcome::run(config, pool, &event_id).await
+        }
+        Commands::ListGroups => commands::list_groups::run(config).await,
+        Commands::Send { group_id, message, min_acks } => {
+            commands::send::run(config, pool, &group_id, &message, min_acks).await
+        }
+        Commands::Dm { peer, message, min_acks } => {
+            commands::dm::run(config, pool, &peer, &message, min_acks).await
+        }
+        Commands::Receive { group_id, since, watch, poll_interval, max_events, include_own } => {
+            let options = commands::receive::ReceiveOptions {
+                group_id: group_id.as_deref(),
+                since: since.as_deref(),
+                watch,
+                poll_interval,
+                max_events,
+                include_own,
+            };
+            commands::receive::run(config, pool, options).await
+        }
+        Commands::Whoami => commands::whoami::run(config).await,
+        Commands::Key { action } => match action {
+            KeyAction::Encrypt => commands::key::encrypt(config).await,
+            KeyAction::Decrypt => commands::key::decrypt(config).await,
+            KeyAction::ChangePassphrase { new_passphrase_file } => {
+                commands::key::change_passphrase(config, new_passphrase_file.as_deref()).await
+            }
+        },
+        Commands::Db { action } => match action {
+            DbAction::Encrypt { mode } => commands::db::encrypt(config, mode).await,
+        },
+        Commands::Relays { action } => match action {
+            RelaysAction::Check { write_test } => commands::relays::check(config, write_test).await,
+        },
+        Commands::Alias { group_id, name } => {
+            commands::alias::run(config, &group_id, &name).await
         }
-        Commands::ListGroups => commands::list_groups::run(&config).await,
-        Commands::Send { group_id, message } => {
-            commands::send::run(&config, &group_id, &message).await
+        Commands::Unalias { name } => commands::alias::run_remove(config, &name).await,
+        Commands::Config { action: ConfigAction::Get { key } } => {
+            commands::config::get(config, &key).await
         }
-        Commands::Receive { group_id, since, watch, poll_interval } => {
-            commands::receive::run(&config, group_id.as_deref(), since.as_deref(), watch, poll_interval).await
+        Commands::Config { action: ConfigAction::List } => commands::config::list(config).await,
+        Commands::Batch => bail!("batch cannot be nested"),
+        Commands::Profile { .. } | Commands::Config { .. } => {
+            bail!("This command edits the config file and cannot be run in a batch")
         }
-        Commands::Whoami => commands::whoami::run(&config).await,
     }
 }
@@ -1,84 +1,160 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use mdk_core::prelude::*;
 use mdk_sqlite_storage::MdkSqliteStorage;
+use nostr_sdk::nips::nip49::{EncryptedSecretKey, KeySecurity};
 use nostr_sdk::prelude::*;
 use nostr_sdk::ToBech32;
+use std::io::Write;
 use std::path::Path;
+use std::sync::Arc;
 
 use crate::config::Config;
+use crate::passphrase::read_passphrase;
+use crate::signer::{self, load_identity, Identity};
+use crate::storage::open_storage;
 
 pub struct MdkContext {
     pub mdk: MDK<MdkSqliteStorage>,
-    pub keys: Keys,
-    pub relays: Vec<RelayUrl>,
+    pub identity: Identity,
 }
 
 impl MdkContext {
-    pub fn load(config: &Config) -> Result<Self> {
-        let keys = load_keys(config)?;
-        let storage = MdkSqliteStorage::new_unencrypted(&config.db_path)
-            .context("Failed to initialize MDK SQLite storage")?;
+    pub async fn load(config: &Config) -> Result<Self> {
+        let identity = load_identity(config).await?;
+        let storage = open_storage(config, identity.keys.as_ref())?;
         let mdk = MDK::new(storage);
-        let relays = config
-            .relays
-            .iter()
-            .filter_map(|r| RelayUrl::parse(r).ok())
-            .collect();
 
-        Ok(Self { mdk, keys, relays })
+        Ok(Self { mdk, identity })
+    }
+
+    pub fn signer(&self) -> Arc<dyn NostrSigner> {
+        self.identity.signer.clone()
     }
 
     pub fn pubkey(&self) -> PublicKey {
-        self.keys.public_key()
+        self.identity.pubkey
     }
 
     pub fn npub(&self) -> String {
-        self.keys.public_key().to_bech32().unwrap_or_default()
+        self.identity.pubkey.to_bech32().unwrap_or_default()
+    }
+
+    pub async fn sign(&self, builder: EventBuilder) -> Result<Event> {
+        signer::sign_event(self.identity.signer.as_ref(), builder.build(self.pubkey())).await
+    }
+
+    pub async fn extract_rumor(&self, gift_wrap: &Event) -> Result<(PublicKey, UnsignedEvent)> {
+        signer::extract_rumor(self.identity.signer.as_ref(), gift_wrap).await
+    }
+
+    pub async fn gift_wrap(&self, receiver: &PublicKey, rumor: UnsignedEvent) -> Result<Event> {
+        signer::gift_wrap(self.identity.signer.as_ref(), &self.pubkey(), receiver, rumor).await
     }
 }
 
+/// scrypt work factor for NIP-49 encryption (2^16 rounds, as recommended by the NIP).
+const NCRYPTSEC_LOG_N: u8 = 16;
+
 pub fn load_keys(config: &Config) -> Result<Keys> {
     let key_file = config
         .key_file
         .as_ref()
         .context("No key file specified. Run 'mdk init' first or use --key-file")?;
 
-    let content = std::fs::read_to_string(key_file)
-        .with_context(|| format!("Failed to read key file: {:?}", key_file))?;
-
-    let secret_key = parse_secret_key(content.trim())?;
+    let secret_key = read_key_file(config, key_file)?;
     Ok(Keys::new(secret_key))
 }
 
+/// Read a key file holding an nsec, hex secret or NIP-49 ncryptsec, decrypting the latter.
+pub fn read_key_file(config: &Config, path: &Path) -> Result<SecretKey> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read key file: {:?}", path))?;
+    let content = content.trim();
+
+    if is_encrypted_key(content) {
+        let passphrase = read_passphrase(config, "Key passphrase: ")?;
+        decrypt_secret_key(content, &passphrase)
+    } else {
+        parse_secret_key(content)
+    }
+}
+
+pub fn is_encrypted_key(input: &str) -> bool {
+    input.starts_with("ncryptsec")
+}
+
 pub fn parse_secret_key(input: &str) -> Result<SecretKey> {
     if input.starts_with("nsec") {
         SecretKey::from_bech32(input).context("Invalid nsec format")
+    } else if is_encrypted_key(input) {
+        bail!("Key is encrypted (ncryptsec); a passphrase is required")
     } else {
         SecretKey::from_hex(input).context("Invalid hex secret key")
     }
 }
 
+pub fn decrypt_secret_key(ncryptsec: &str, passphrase: &str) -> Result<SecretKey> {
+    let encrypted = EncryptedSecretKey::from_bech32(ncryptsec).context("Invalid ncryptsec format")?;
+    encrypted
+        .decrypt(passphrase)
+        .context("Failed to decrypt key (wrong passphrase?)")
+}
+
 pub fn generate_keys() -> Keys {
     Keys::generate()
 }
 
-pub fn save_keys(keys: &Keys, path: &Path) -> Result<()> {
+/// Write the secret key to `path`, as an ncryptsec when a passphrase is given
+/// and as raw hex otherwise.
+pub fn save_keys(keys: &Keys, path: &Path, passphrase: Option<&str>) -> Result<()> {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)
             .with_context(|| format!("Failed to create directory: {:?}", parent))?;
     }
 
-    let secret_hex = keys.secret_key().to_secret_hex();
-    std::fs::write(path, &secret_hex)
-        .with_context(|| format!("Failed to write key file: {:?}", path))?;
+    let content = match passphrase {
+        Some(passphrase) => EncryptedSecretKey::new(
+            keys.secret_key(),
+            passphrase,
+            NCRYPTSEC_LOG_N,
+            KeySecurity::Unknown,
+        )
+        .context("Failed to encrypt secret key")?
+        .to_bech32()
+        .context("Failed to encode ncryptsec")?,
+        None => keys.secret_key().to_secret_hex(),
+    };
+
+    // Write beside the target and rename so an interrupted write never loses the key.
+    // The file is created owner-only, so the secret is never readable by others;
+    // a leftover temp file is removed first since `mode` only applies on creation.
+    let tmp_path = path.with_extension("key.tmp");
+    match std::fs::remove_file(&tmp_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(e).with_context(|| format!("Failed to remove {:?}", tmp_path));
+        }
+        _ => {}
+    }
 
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
     #[cfg(unix)]
     {
-        use std::os::unix::fs::PermissionsExt;
-        let perms = std::fs::Permissions::from_mode(0o600);
-        std::fs::set_permissions(path, perms)?;
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    {
+        let mut file = options
+            .open(&tmp_path)
+            .with_context(|| format!("Failed to create key file: {:?}", tmp_path))?;
+        file.write_all(content.as_bytes())
+            .and_then(|_| file.sync_all())
+            .with_context(|| format!("Failed to write key file: {:?}", tmp_path))?;
     }
 
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to write key file: {:?}", path))?;
+
     Ok(())
 }
 
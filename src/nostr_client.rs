@@ -1,48 +1,600 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use futures::future::join_all;
 use nostr_sdk::prelude::*;
-use std::time::Duration;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::OnceCell;
 
+use crate::relay_auth::{is_auth_required, AuthFailure, RelayAuthenticator};
+use crate::relays::{RelayConfig, RelayRole};
+
+/// A command's view of the relay connections: role-based calls use only the
+/// relays this command was given, and reports cover only the relays it used.
 pub struct NostrClient {
+    shared: Arc<SharedClient>,
+    relays: Vec<RelayConfig>,
+    /// Every relay this command has connected to or tried to.
+    used: Mutex<BTreeSet<RelayUrl>>,
+}
+
+/// How long a failed connection attempt stands before the relay is dialled
+/// again on its next use.
+const CONNECT_RETRY_SECS: u64 = 30;
+
+type ConnectAttempt = Arc<OnceCell<Result<ConnectTiming, ConnectFailure>>>;
+
+/// How long a successful connection took, split into its two phases.
+#[derive(Clone, Copy, Default)]
+pub struct ConnectTiming {
+    /// Opening the WebSocket.
+    pub connect: Duration,
+    /// Waiting afterwards for the relay's AUTH challenge (auth-enabled relays only).
+    pub auth: Duration,
+}
+
+struct ConnectFailure {
+    error: String,
+    at: Instant,
+}
+
+/// The connections themselves, shared by the commands of one process.
+struct SharedClient {
     client: Client,
-    relays: Vec<String>,
+    /// The current connection attempt per relay, made on first use; later
+    /// callers wait on the same attempt instead of dialling again. A failed
+    /// attempt is replaced once it is [`CONNECT_RETRY_SECS`] old.
+    attempts: Mutex<HashMap<RelayUrl, ConnectAttempt>>,
+    connect_timeout: Duration,
+    auth: RelayAuthenticator,
+}
+
+impl SharedClient {
+    fn new(signer: Arc<dyn NostrSigner>, options: ConnectOptions) -> Self {
+        // AUTH is answered per relay by `RelayAuthenticator`, only where enabled in config.
+        let mut opts = ClientOptions::new().automatic_authentication(false);
+        if let Some(proxy) = options.proxy {
+            opts = opts.connection(Connection::new().proxy(proxy).target(ConnectionTarget::All));
+        }
+        let client = Client::builder().signer(signer.clone()).opts(opts).build();
+        let auth = RelayAuthenticator::spawn(&client, signer, HashSet::new());
+
+        Self {
+            client,
+            attempts: Mutex::new(HashMap::new()),
+            connect_timeout: options.timeout,
+            auth,
+        }
+    }
+
+    /// Connect to `url` and wait for its AUTH, if enabled, to settle.
+    async fn connect(&self, url: &RelayUrl) -> Result<ConnectTiming, ConnectFailure> {
+        let failure = |error: String| ConnectFailure { error, at: Instant::now() };
+        self.client.add_relay(url).await.map_err(|e| failure(e.to_string()))?;
+        let start = Instant::now();
+        if let Err(e) = self.client.try_connect_relay(url, self.connect_timeout).await {
+            tracing::warn!("Relay unreachable: {} ({})", url, e);
+            return Err(failure(e.to_string()));
+        }
+        let connected = Instant::now();
+        self.auth.wait(std::slice::from_ref(url), self.connect_timeout).await;
+        Ok(ConnectTiming { connect: connected - start, auth: connected.elapsed() })
+    }
+
+    /// Why `url` could not be connected to, if it was tried and failed.
+    fn connect_error(&self, url: &RelayUrl) -> Option<String> {
+        let attempts = self.attempts.lock().unwrap();
+        match attempts.get(url)?.get()? {
+            Ok(_) => None,
+            Err(failure) => Some(failure.error.clone()),
+        }
+    }
+}
+
+/// Relay connections shared by every command run in this process (one, or
+/// several in `marmot batch`). Each command gets its own `NostrClient` over them.
+#[derive(Default)]
+pub struct RelayPool {
+    shared: Option<Arc<SharedClient>>,
+}
+
+impl RelayPool {
+    /// A client for one command using `relays`. `signer` and `options` only
+    /// apply when the first client is created.
+    pub fn client(
+        &mut self,
+        signer: Arc<dyn NostrSigner>,
+        relays: Vec<RelayConfig>,
+        options: ConnectOptions,
+    ) -> NostrClient {
+        let shared = self
+            .shared
+            .get_or_insert_with(|| Arc::new(SharedClient::new(signer, options)))
+            .clone();
+        NostrClient::with_shared(shared, relays)
+    }
+
+    pub async fn shutdown(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            shared.client.disconnect().await;
+        }
+    }
+}
+
+/// Settings applied to every relay connection.
+#[derive(Clone, Copy)]
+pub struct ConnectOptions {
+    pub timeout: Duration,
+    /// SOCKS5 proxy that all relay traffic goes through; there is no direct fallback.
+    pub proxy: Option<SocketAddr>,
+}
+
+/// A configured relay that could not be connected to within the connect timeout.
+#[derive(Serialize, Clone)]
+pub struct UnreachableRelay {
+    pub url: String,
+    pub error: String,
+}
+
+/// Outcome of publishing an event to a single relay.
+#[derive(Serialize, Clone)]
+pub struct RelayPublishResult {
+    pub url: String,
+    pub accepted: bool,
+    pub message: Option<String>,
+}
+
+pub struct PublishResult {
+    pub event_id: EventId,
+    pub relays: Vec<RelayPublishResult>,
+}
+
+impl PublishResult {
+    pub fn accepted_count(&self) -> usize {
+        self.relays.iter().filter(|r| r.accepted).count()
+    }
+
+    /// Fail if fewer than `min_acks` relays accepted the event.
+    pub fn require_acks(&self, min_acks: usize) -> Result<()> {
+        let accepted = self.accepted_count();
+        if accepted >= min_acks {
+            return Ok(());
+        }
+
+        let rejections: Vec<String> = self
+            .relays
+            .iter()
+            .filter(|r| !r.accepted)
+            .map(|r| match &r.message {
+                Some(msg) => format!("{}: {}", r.url, msg),
+                None => r.url.clone(),
+            })
+            .collect();
+
+        bail!(
+            "Event {} accepted by {} relay(s), {} required (rejected: {})",
+            self.event_id.to_hex(),
+            accepted,
+            min_acks,
+            if rejections.is_empty() { "none".to_string() } else { rejections.join("; ") }
+        );
+    }
 }
 
 impl NostrClient {
-    pub async fn new(keys: &Keys, relays: Vec<String>) -> Result<Self> {
-        let client = Client::new(keys.clone());
+    /// Create a standalone client for `relays`. Nothing is connected until a relay is first used.
+    pub fn new(signer: Arc<dyn NostrSigner>, relays: Vec<RelayConfig>, options: ConnectOptions) -> Self {
+        Self::with_shared(Arc::new(SharedClient::new(signer, options)), relays)
+    }
+
+    fn with_shared(shared: Arc<SharedClient>, relays: Vec<RelayConfig>) -> Self {
+        let mut client = Self {
+            shared,
+            relays: Vec::new(),
+            used: Mutex::new(BTreeSet::new()),
+        };
+        client.add_relays(relays);
+        client
+    }
+
+    /// Add relays for the rest of this command, e.g. those of a newly joined group.
+    /// They are connected on first use.
+    pub fn add_relays(&mut self, relays: Vec<RelayConfig>) {
+        self.shared.auth.enable(relays.iter().filter(|r| r.auth).map(|r| r.url.clone()));
+
+        for relay in relays {
+            match self.relays.iter_mut().find(|r| r.url == relay.url) {
+                Some(existing) => {
+                    existing.roles.extend(relay.roles);
+                    existing.auth |= relay.auth;
+                }
+                None => self.relays.push(relay),
+            }
+        }
+    }
 
-        for relay in &relays {
-            client.add_relay(relay).await?;
+    /// Connect to every relay, failing if none is reachable.
+    pub async fn connect(&self) -> Result<()> {
+        let urls: Vec<RelayUrl> = self.relays.iter().map(|relay| relay.url.clone()).collect();
+        let connected = self.connect_urls(&urls).await;
+        if connected.is_empty() && !urls.is_empty() {
+            bail!(self.unreachable_error(&urls, "any relay"));
         }
+        Ok(())
+    }
+
+    /// Relays serving `role` that could be connected to, connecting on first use.
+    async fn urls(&self, role: RelayRole) -> Result<Vec<RelayUrl>> {
+        let urls: Vec<RelayUrl> = self
+            .relays
+            .iter()
+            .filter(|relay| relay.has_role(role))
+            .map(|relay| relay.url.clone())
+            .collect();
+        if urls.is_empty() {
+            bail!("No relay configured with the '{}' role", role);
+        }
+
+        let connected = self.connect_urls(&urls).await;
+        if connected.is_empty() {
+            bail!(self.unreachable_error(&urls, &format!("any '{}' relay", role)));
+        }
+        Ok(connected)
+    }
+
+    /// Connect to any of `urls` not tried yet (in parallel), returning those
+    /// that are reachable. Relays outside the configured set (e.g. another
+    /// user's inbox relays) are allowed.
+    async fn connect_urls(&self, urls: &[RelayUrl]) -> Vec<RelayUrl> {
+        self.used.lock().unwrap().extend(urls.iter().cloned());
 
-        client.connect().await;
+        let cells: Vec<ConnectAttempt> = {
+            let mut attempts = self.shared.attempts.lock().unwrap();
+            urls.iter()
+                .map(|url| {
+                    let attempt = attempts.entry(url.clone()).or_default();
+                    if let Some(Err(failure)) = attempt.get() {
+                        if failure.at.elapsed() >= Duration::from_secs(CONNECT_RETRY_SECS) {
+                            *attempt = ConnectAttempt::default();
+                        }
+                    }
+                    attempt.clone()
+                })
+                .collect()
+        };
 
-        tokio::time::sleep(Duration::from_millis(500)).await;
+        let results = join_all(
+            urls.iter()
+                .zip(&cells)
+                .map(|(url, cell)| cell.get_or_init(|| self.shared.connect(url))),
+        )
+        .await;
 
-        Ok(Self { client, relays })
+        urls.iter()
+            .zip(results)
+            .filter(|(_, result)| result.is_ok())
+            .map(|(url, _)| url.clone())
+            .collect()
     }
 
-    pub fn client(&self) -> &Client {
-        &self.client
+    fn unreachable_error(&self, urls: &[RelayUrl], what: &str) -> String {
+        let errors: Vec<String> = urls
+            .iter()
+            .filter_map(|url| Some(format!("{}: {}", url, self.shared.connect_error(url)?)))
+            .collect();
+        format!(
+            "Could not connect to {} within {}s ({})",
+            what,
+            self.shared.connect_timeout.as_secs(),
+            errors.join("; ")
+        )
     }
 
-    pub fn relays(&self) -> &[String] {
-        &self.relays
+    /// Relays used by this command that could not be connected to.
+    pub fn unreachable(&self) -> Vec<UnreachableRelay> {
+        self.used
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|url| {
+                Some(UnreachableRelay {
+                    url: url.to_string(),
+                    error: self.shared.connect_error(url)?,
+                })
+            })
+            .collect()
     }
 
-    pub async fn publish(&self, event: Event) -> Result<EventId> {
-        let output = self.client.send_event(&event).await?;
-        Ok(output.id().clone())
+    /// How long connecting to `url` took, if it has been connected.
+    pub fn connect_timing(&self, url: &RelayUrl) -> Option<ConnectTiming> {
+        let attempts = self.shared.attempts.lock().unwrap();
+        attempts.get(url)?.get()?.as_ref().ok().copied()
     }
 
-    pub async fn fetch_events(&self, filter: Filter, timeout: Duration) -> Result<Vec<Event>> {
-        let events = self.client
-            .fetch_events(filter, timeout)
-            .await?;
-        Ok(events.into_iter().collect())
+    /// Relays used by this command where NIP-42 authentication failed.
+    pub fn auth_failures(&self) -> Vec<AuthFailure> {
+        let used = self.used.lock().unwrap();
+        self.shared
+            .auth
+            .failures()
+            .into_iter()
+            .filter(|failure| used.iter().any(|url| url.as_str() == failure.url))
+            .collect()
+    }
+
+    /// Publish an event to the relays serving `role`, waiting for each relay's OK.
+    pub async fn publish(&self, role: RelayRole, event: Event) -> Result<PublishResult> {
+        let urls = self.urls(role).await?;
+        self.send_event(urls, &event).await
+    }
+
+    /// Publish an event to explicit relays, which need not be configured.
+    pub async fn publish_to(&self, urls: &[RelayUrl], event: Event) -> Result<PublishResult> {
+        if urls.is_empty() {
+            bail!("No relays to publish event {} to", event.id.to_hex());
+        }
+        self.connect_urls(urls).await;
+        self.send_event(urls.to_vec(), &event).await
+    }
+
+    /// Send `event`, sending it again to relays that rejected it with
+    /// `auth-required:` once they have authenticated us.
+    async fn send_event(&self, urls: Vec<RelayUrl>, event: &Event) -> Result<PublishResult> {
+        let mut output = self.shared.client.send_event_to(urls, event).await?;
+
+        let auth_required: Vec<RelayUrl> = output
+            .failed
+            .iter()
+            .filter(|(_, message)| is_auth_required(message))
+            .map(|(url, _)| url.clone())
+            .collect();
+        let retry = self.shared.auth.authenticated(&auth_required, self.shared.connect_timeout).await;
+        if !retry.is_empty() {
+            let again = self.shared.client.send_event_to(retry, event).await?;
+            for url in again.success {
+                output.failed.remove(&url);
+                output.success.insert(url);
+            }
+            output.failed.extend(again.failed);
+        }
+
+        Ok(publish_result(output))
+    }
+
+    pub async fn fetch_events(
+        &self,
+        role: RelayRole,
+        filter: Filter,
+        timeout: Duration,
+    ) -> Result<Vec<Event>> {
+        let urls = self.urls(role).await?;
+        self.query(urls, filter, timeout).await
+    }
+
+    /// Fetch events from explicit relays, which need not be configured.
+    pub async fn fetch_events_from(
+        &self,
+        urls: &[RelayUrl],
+        filter: Filter,
+        timeout: Duration,
+    ) -> Result<Vec<Event>> {
+        if urls.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.connect_urls(urls).await;
+        self.query(urls.to_vec(), filter, timeout).await
+    }
+
+    /// Fetch from `urls`, querying again the relays that asked for AUTH while
+    /// the request ran (a challenge or an `auth-required:` CLOSED) once they
+    /// have authenticated us.
+    async fn query(&self, urls: Vec<RelayUrl>, filter: Filter, timeout: Duration) -> Result<Vec<Event>> {
+        // Subscribed before the request so every message it triggers is seen;
+        // each query watches its own copy, so concurrent queries don't interfere.
+        let mut notifications = self.shared.client.notifications();
+        let mut events: Vec<Event> = self
+            .shared
+            .client
+            .fetch_events_from(urls.clone(), filter.clone(), timeout)
+            .await?
+            .into_iter()
+            .collect();
+
+        let asked_for_auth = auth_requests(&mut notifications, &urls);
+        let retry = self.shared.auth.authenticated(&asked_for_auth, self.shared.connect_timeout).await;
+        if !retry.is_empty() {
+            let mut seen: HashSet<EventId> = events.iter().map(|event| event.id).collect();
+            let more = self.shared.client.fetch_events_from(retry, filter, timeout).await?;
+            events.extend(more.into_iter().filter(|event| seen.insert(event.id)));
+        }
+
+        Ok(events)
+    }
+
+    /// Fetch every event matching `filter`, oldest first. Relays return the
+    /// newest events first, so each relay is paged backwards with `until` on
+    /// its own until it runs out; callers bound how many they process, not
+    /// how many are fetched.
+    pub async fn fetch_all_events(
+        &self,
+        role: RelayRole,
+        filter: Filter,
+        page_size: usize,
+        timeout: Duration,
+    ) -> Result<Vec<Event>> {
+        let urls = self.urls(role).await?;
+        let backlogs = join_all(
+            urls.iter()
+                .map(|url| self.fetch_relay_backlog(url, &filter, page_size.max(1), timeout)),
+        )
+        .await;
+
+        let mut seen: HashSet<EventId> = HashSet::new();
+        let mut events: Vec<Event> = Vec::new();
+        for backlog in backlogs {
+            events.extend(backlog?.into_iter().filter(|event| seen.insert(event.id)));
+        }
+
+        events.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+
+        Ok(events)
+    }
+
+    /// Page one relay backwards until it returns nothing new. A relay may cap
+    /// `limit` below `page_size`, so a short page does not mean it ran out.
+    async fn fetch_relay_backlog(
+        &self,
+        url: &RelayUrl,
+        filter: &Filter,
+        page_size: usize,
+        timeout: Duration,
+    ) -> Result<Vec<Event>> {
+        let urls = vec![url.clone()];
+        let mut seen: HashSet<EventId> = HashSet::new();
+        let mut events: Vec<Event> = Vec::new();
+        let mut until: Option<Timestamp> = None;
+
+        loop {
+            let mut page_filter = filter.clone().limit(page_size);
+            if let Some(ts) = until {
+                page_filter = page_filter.until(ts);
+            }
+
+            let page = self.query(urls.clone(), page_filter, timeout).await?;
+
+            let mut added = 0;
+            let mut oldest: Option<Timestamp> = None;
+            for event in page {
+                oldest = Some(oldest.map_or(event.created_at, |ts| ts.min(event.created_at)));
+                if seen.insert(event.id) {
+                    events.push(event);
+                    added += 1;
+                }
+            }
+
+            let Some(oldest) = oldest else {
+                break;
+            };
+
+            if added > 0 {
+                // `until` is inclusive, so the oldest second is fetched again
+                // and any of its events cut off by the limit are picked up.
+                until = Some(oldest);
+                continue;
+            }
+
+            // A page with nothing new: the oldest second holds more than a
+            // page of events. Fetch that second on its own, then step past it.
+            let second = filter.clone().since(oldest).until(oldest);
+            for event in self.query(urls.clone(), second, timeout).await? {
+                if seen.insert(event.id) {
+                    events.push(event);
+                }
+            }
+            match oldest.as_secs().checked_sub(1) {
+                Some(secs) => until = Some(Timestamp::from_secs(secs)),
+                None => break,
+            }
+        }
+
+        Ok(events)
+    }
+
+    pub async fn subscribe(&self, role: RelayRole, filter: Filter) -> Result<SubscriptionId> {
+        let output = self.shared.client.subscribe_to(self.urls(role).await?, filter, None).await?;
+        Ok(output.val)
+    }
+
+    pub async fn unsubscribe(&self, id: &SubscriptionId) {
+        self.shared.client.unsubscribe(id).await;
+    }
+
+    pub fn notifications(&self) -> tokio::sync::broadcast::Receiver<RelayPoolNotification> {
+        self.shared.client.notifications()
+    }
+
+    /// How many of the relays used by this command are connected.
+    pub async fn connected_count(&self) -> usize {
+        let used = self.used.lock().unwrap().clone();
+        self.shared
+            .client
+            .relays()
+            .await
+            .iter()
+            .filter(|(url, relay)| used.contains(*url) && relay.is_connected())
+            .count()
+    }
+
+    /// Try to re-establish dropped relay connections, returning how many are connected.
+    pub async fn reconnect(&self, timeout: Duration) -> usize {
+        let output = self.shared.client.try_connect(timeout).await;
+        for (url, error) in &output.failed {
+            tracing::debug!("Reconnect to {} failed: {}", url, error);
+        }
+        {
+            let mut attempts = self.shared.attempts.lock().unwrap();
+            for url in output.success {
+                attempts.insert(url, Arc::new(OnceCell::new_with(Some(Ok(ConnectTiming::default())))));
+            }
+        }
+        self.connected_count().await
     }
 
     pub async fn disconnect(&self) {
-        self.client.disconnect().await;
+        self.shared.client.disconnect().await;
+    }
+}
+
+/// Which of `urls` sent an AUTH challenge or an `auth-required:` CLOSED among
+/// the notifications received so far. If some were dropped, all of `urls` count.
+fn auth_requests(
+    notifications: &mut tokio::sync::broadcast::Receiver<RelayPoolNotification>,
+    urls: &[RelayUrl],
+) -> Vec<RelayUrl> {
+    let mut asked: Vec<RelayUrl> = Vec::new();
+    loop {
+        let (relay_url, message) = match notifications.try_recv() {
+            Ok(RelayPoolNotification::Message { relay_url, message }) => (relay_url, message),
+            Ok(_) => continue,
+            Err(TryRecvError::Lagged(_)) => return urls.to_vec(),
+            Err(_) => break,
+        };
+        let asks = match &message {
+            RelayMessage::Auth { .. } => true,
+            RelayMessage::Closed { message, .. } => is_auth_required(message),
+            _ => false,
+        };
+        if asks && urls.contains(&relay_url) && !asked.contains(&relay_url) {
+            asked.push(relay_url);
+        }
+    }
+    asked
+}
+
+fn publish_result(output: Output<EventId>) -> PublishResult {
+    let mut relays: Vec<RelayPublishResult> = output
+        .success
+        .iter()
+        .map(|url| RelayPublishResult {
+            url: url.to_string(),
+            accepted: true,
+            message: None,
+        })
+        .collect();
+
+    relays.extend(output.failed.iter().map(|(url, msg)| RelayPublishResult {
+        url: url.to_string(),
+        accepted: false,
+        message: Some(msg.clone()),
+    }));
+
+    relays.sort_by(|a, b| a.url.cmp(&b.url));
+
+    PublishResult {
+        event_id: *output.id(),
+        relays,
     }
 }
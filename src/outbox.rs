@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+use nostr_sdk::prelude::*;
+use std::time::Duration;
+
+use crate::nostr_client::NostrClient;
+use crate::relays::RelayRole;
+
+/// NIP-17 relays for receiving gift-wrapped events.
+pub const KIND_INBOX_RELAYS: u16 = 10050;
+/// NIP-65 read/write relay list.
+pub const KIND_RELAY_LIST: u16 = 10002;
+/// Marmot (MIP-00) relays where a user publishes key packages.
+pub const KIND_KEY_PACKAGE_RELAYS: u16 = 10051;
+
+const FETCH_TIMEOUT_SECS: u64 = 10;
+
+/// The newest relay lists another user has published (outbox model), looked up
+/// on our key package relays.
+pub struct RelayLists {
+    inbox: Option<Event>,
+    key_packages: Option<Event>,
+    relay_list: Option<Event>,
+}
+
+impl RelayLists {
+    pub async fn fetch(nostr: &NostrClient, pubkey: &PublicKey) -> Result<Self> {
+        let filter = Filter::new().author(*pubkey).kinds([
+            Kind::Custom(KIND_INBOX_RELAYS),
+            Kind::Custom(KIND_RELAY_LIST),
+            Kind::Custom(KIND_KEY_PACKAGE_RELAYS),
+        ]);
+
+        let events = nostr
+            .fetch_events(RelayRole::KeyPackages, filter, Duration::from_secs(FETCH_TIMEOUT_SECS))
+            .await
+            .context("Failed to fetch relay lists")?;
+
+        let newest = |kind: u16| {
+            events
+                .iter()
+                .filter(|e| e.kind == Kind::Custom(kind))
+                .max_by_key(|e| e.created_at)
+                .cloned()
+        };
+
+        Ok(Self {
+            inbox: newest(KIND_INBOX_RELAYS),
+            key_packages: newest(KIND_KEY_PACKAGE_RELAYS),
+            relay_list: newest(KIND_RELAY_LIST),
+        })
+    }
+
+    /// Where to deliver gift-wraps: the kind 10050 list, else NIP-65 read relays.
+    pub fn inbox_relays(&self) -> Vec<RelayUrl> {
+        match &self.inbox {
+            Some(event) => relay_tags(event),
+            None => self.nip65_relays("read"),
+        }
+    }
+
+    /// Where to look for key packages: the kind 10051 list, else NIP-65 write relays.
+    pub fn key_package_relays(&self) -> Vec<RelayUrl> {
+        match &self.key_packages {
+            Some(event) => relay_tags(event),
+            None => self.nip65_relays("write"),
+        }
+    }
+
+    /// NIP-65 `r` tags usable for `marker`; an unmarked relay is both read and write.
+    fn nip65_relays(&self, marker: &str) -> Vec<RelayUrl> {
+        let Some(event) = &self.relay_list else {
+            return Vec::new();
+        };
+
+        event
+            .tags
+            .iter()
+            .filter_map(|tag| match tag.as_slice() {
+                [name, url] if name == "r" => Some(url),
+                [name, url, m] if name == "r" && m == marker => Some(url),
+                _ => None,
+            })
+            .filter_map(|url| RelayUrl::parse(url).ok())
+            .collect()
+    }
+}
+
+/// The `relay` tags of a kind 10050 or 10051 list.
+fn relay_tags(event: &Event) -> Vec<RelayUrl> {
+    event
+        .tags
+        .iter()
+        .filter_map(|tag| match tag.as_slice() {
+            [name, url, ..] if name == "relay" => RelayUrl::parse(url).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Builder for our kind 10050 list advertising `relays` as our inbox.
+pub fn inbox_relays_event(relays: &[RelayUrl]) -> EventBuilder {
+    let tags = relays
+        .iter()
+        .map(|url| Tag::custom(TagKind::custom("relay"), [url.to_string()]));
+    EventBuilder::new(Kind::Custom(KIND_INBOX_RELAYS), "").tags(tags)
+}
+
+/// Inbox relays we have advertised that are not among our configured inbox
+/// relays; senders following our kind 10050 list deliver there too.
+pub async fn extra_advertised_inbox_relays(
+    nostr: &NostrClient,
+    configured: &[RelayUrl],
+    pubkey: &PublicKey,
+) -> Vec<RelayUrl> {
+    match RelayLists::fetch(nostr, pubkey).await {
+        Ok(lists) => lists
+            .inbox_relays()
+            .into_iter()
+            .filter(|url| !configured.contains(url))
+            .collect(),
+        Err(e) => {
+            tracing::debug!("Could not look up our advertised inbox relays: {:#}", e);
+            Vec::new()
+        }
+    }
+}
@@ -5,6 +5,8 @@ pub struct Output<T: Serialize> {
     pub success: bool,
     pub data: Option<T>,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
 }
 
 impl<T: Serialize> Output<T> {
@@ -13,6 +15,7 @@ impl<T: Serialize> Output<T> {
             success: true,
             data: Some(data),
             error: None,
+            details: None,
         }
     }
 
@@ -21,6 +24,7 @@ impl<T: Serialize> Output<T> {
             success: false,
             data: None,
             error: Some(msg.into()),
+            details: None,
         }
     }
 }
@@ -35,6 +39,13 @@ pub fn print_error(err: impl std::fmt::Display) {
     println!("{}", serde_json::to_string_pretty(&output).unwrap());
 }
 
+/// Print an error along with structured context (e.g. candidate matches).
+pub fn print_error_details(err: impl std::fmt::Display, details: impl Serialize) {
+    let mut output: Output<()> = Output::err(err.to_string());
+    output.details = serde_json::to_value(details).ok();
+    println!("{}", serde_json::to_string_pretty(&output).unwrap());
+}
+
 pub fn print_success<T: Serialize>(data: T) {
     print_json(data);
 }
@@ -0,0 +1,86 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+use crate::config::Config;
+
+pub const PASSPHRASE_ENV: &str = "MDK_KEY_PASSPHRASE";
+pub const NEW_PASSPHRASE_ENV: &str = "MDK_NEW_KEY_PASSPHRASE";
+/// The database passphrase is separate from the key passphrase: the key's
+/// env var and `--passphrase-file` are never used for it.
+pub const DB_PASSPHRASE_ENV: &str = "MDK_DB_PASSPHRASE";
+
+const KEY_HINT: &str = "set MDK_KEY_PASSPHRASE or use --passphrase-file";
+const DB_HINT: &str = "set MDK_DB_PASSPHRASE";
+
+/// Passphrase for an existing encrypted key: `MDK_KEY_PASSPHRASE`, then
+/// `--passphrase-file`, then an interactive prompt.
+pub fn read_passphrase(config: &Config, prompt: &str) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    if let Some(path) = &config.passphrase_file {
+        return read_passphrase_file(path);
+    }
+
+    prompt_passphrase(prompt, KEY_HINT)
+}
+
+/// Passphrase for encrypting a key, from `env_var`, then `file`, then a prompt.
+/// Non-interactive sources are taken as-is; the prompt asks twice.
+pub fn read_new_passphrase(env_var: &str, file: Option<&Path>) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(env_var) {
+        return non_empty(passphrase);
+    }
+
+    if let Some(path) = file {
+        return non_empty(read_passphrase_file(path)?);
+    }
+
+    prompt_new_passphrase("New key passphrase: ", KEY_HINT)
+}
+
+/// Passphrase for an existing passphrase-encrypted database: `MDK_DB_PASSPHRASE`,
+/// then an interactive prompt.
+pub fn read_db_passphrase() -> Result<String> {
+    if let Ok(passphrase) = std::env::var(DB_PASSPHRASE_ENV) {
+        return non_empty(passphrase);
+    }
+    non_empty(prompt_passphrase("Database passphrase: ", DB_HINT)?)
+}
+
+/// Passphrase for encrypting the database: `MDK_DB_PASSPHRASE`, else a prompt
+/// that asks twice, since a typo would leave the database unreadable.
+pub fn read_new_db_passphrase() -> Result<String> {
+    if let Ok(passphrase) = std::env::var(DB_PASSPHRASE_ENV) {
+        return non_empty(passphrase);
+    }
+    prompt_new_passphrase("New database passphrase: ", DB_HINT)
+}
+
+fn prompt_new_passphrase(prompt: &str, hint: &str) -> Result<String> {
+    let passphrase = prompt_passphrase(prompt, hint)?;
+    let confirm = prompt_passphrase("Confirm passphrase: ", hint)?;
+    if passphrase != confirm {
+        bail!("Passphrases do not match");
+    }
+    non_empty(passphrase)
+}
+
+fn read_passphrase_file(path: &Path) -> Result<String> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read passphrase file: {:?}", path))?;
+    Ok(content.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn prompt_passphrase(prompt: &str, hint: &str) -> Result<String> {
+    rpassword::prompt_password(prompt)
+        .with_context(|| format!("Failed to read passphrase ({} when not on a terminal)", hint))
+}
+
+fn non_empty(passphrase: String) -> Result<String> {
+    if passphrase.is_empty() {
+        bail!("Passphrase must not be empty");
+    }
+    Ok(passphrase)
+}
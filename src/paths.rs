@@ -0,0 +1,94 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "marmot";
+
+/// `$XDG_CONFIG_HOME/marmot` (platform config dir elsewhere).
+pub fn config_dir() -> Result<PathBuf> {
+    let base = dirs::config_dir().context("Could not determine config directory")?;
+    Ok(base.join(APP_DIR))
+}
+
+/// `$XDG_DATA_HOME/marmot` (platform data dir elsewhere): database, key, cursors, profiles.
+pub fn data_dir() -> Result<PathBuf> {
+    let base = dirs::data_dir().context("Could not determine data directory")?;
+    Ok(base.join(APP_DIR))
+}
+
+pub fn default_config_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("config.toml"))
+}
+
+/// The pre-XDG install location.
+fn legacy_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|h| h.join(".mdk"))
+}
+
+/// Move an existing `~/.mdk` install into the XDG config and data directories,
+/// rewriting paths in its config file. Runs only when no XDG config exists yet.
+pub fn migrate_legacy() -> Result<()> {
+    let Some(legacy) = legacy_dir() else {
+        return Ok(());
+    };
+    let config_path = default_config_path()?;
+    if !legacy.is_dir() || config_path.exists() {
+        return Ok(());
+    }
+
+    let data = data_dir()?;
+    let config = config_dir()?;
+    std::fs::create_dir_all(&data)
+        .with_context(|| format!("Failed to create data directory: {:?}", data))?;
+    std::fs::create_dir_all(&config)
+        .with_context(|| format!("Failed to create config directory: {:?}", config))?;
+
+    for entry in std::fs::read_dir(&legacy).with_context(|| format!("Failed to read {:?}", legacy))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "config.toml" {
+            continue;
+        }
+        let target = data.join(&name);
+        if target.exists() {
+            bail!(
+                "Cannot migrate {:?}: {:?} already exists. Move the remaining files by hand",
+                entry.path(),
+                target
+            );
+        }
+        move_path(&entry.path(), &target)?;
+    }
+
+    let legacy_config = legacy.join("config.toml");
+    if legacy_config.exists() {
+        let content = std::fs::read_to_string(&legacy_config)
+            .with_context(|| format!("Failed to read {:?}", legacy_config))?;
+        let rewritten = content.replace(
+            legacy.to_string_lossy().as_ref(),
+            data.to_string_lossy().as_ref(),
+        );
+        std::fs::write(&config_path, rewritten)
+            .with_context(|| format!("Failed to write {:?}", config_path))?;
+        std::fs::remove_file(&legacy_config)
+            .with_context(|| format!("Failed to remove {:?}", legacy_config))?;
+    }
+
+    let _ = std::fs::remove_dir(&legacy);
+
+    tracing::info!(
+        "Migrated {} to {} (config) and {} (data)",
+        legacy.display(),
+        config.display(),
+        data.display()
+    );
+    Ok(())
+}
+
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+    std::fs::rename(from, to).with_context(|| {
+        format!(
+            "Failed to move {:?} to {:?}; move it by hand (different filesystems?)",
+            from, to
+        )
+    })
+}
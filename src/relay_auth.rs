@@ -0,0 +1,205 @@
+use anyhow::{Context, Result};
+use nostr_sdk::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::signer::sign_event;
+
+/// How long to wait for an AUTH-enabled relay to send its challenge after connecting.
+const CHALLENGE_WAIT_MILLIS: u64 = 2000;
+const POLL_MILLIS: u64 = 100;
+
+/// A relay that rejected (or never confirmed) our NIP-42 AUTH.
+#[derive(Serialize, Clone)]
+pub struct AuthFailure {
+    pub url: String,
+    pub error: String,
+}
+
+enum AuthState {
+    Pending(EventId),
+    Authenticated,
+    Failed(String),
+}
+
+#[derive(Default)]
+struct Shared {
+    enabled: HashSet<RelayUrl>,
+    states: BTreeMap<RelayUrl, AuthState>,
+}
+
+/// Answers NIP-42 AUTH challenges from opted-in relays in the background,
+/// signing with the client's signer, and records the outcome per relay.
+/// Challenges from other relays are ignored so our identity is not revealed to them.
+///
+/// Relays that only challenge on demand reject the first REQ or EVENT with an
+/// `auth-required:` reason; `NostrClient` retries those once AUTH succeeds.
+pub struct RelayAuthenticator {
+    shared: Arc<Mutex<Shared>>,
+    task: JoinHandle<()>,
+}
+
+impl RelayAuthenticator {
+    /// Start listening for challenges. Call before connecting so challenges
+    /// sent on connect are not missed.
+    pub fn spawn(client: &Client, signer: Arc<dyn NostrSigner>, enabled: HashSet<RelayUrl>) -> Self {
+        let shared = Arc::new(Mutex::new(Shared {
+            enabled,
+            ..Shared::default()
+        }));
+
+        let mut notifications = client.notifications();
+        let client = client.clone();
+        let task_shared = shared.clone();
+        let task = tokio::spawn(async move {
+            let mut pending: HashMap<EventId, RelayUrl> = HashMap::new();
+            loop {
+                let (relay_url, message) = match notifications.recv().await {
+                    Ok(RelayPoolNotification::Message { relay_url, message }) => (relay_url, message),
+                    Ok(RelayPoolNotification::Shutdown) => break,
+                    Ok(_) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                };
+
+                match message {
+                    RelayMessage::Auth { challenge } => {
+                        if !task_shared.lock().unwrap().enabled.contains(&relay_url) {
+                            tracing::debug!("Ignoring AUTH challenge from {} (auth not enabled)", relay_url);
+                            continue;
+                        }
+
+                        let state = match send_auth(&client, signer.as_ref(), &relay_url, &challenge).await {
+                            Ok(event_id) => {
+                                pending.insert(event_id, relay_url.clone());
+                                AuthState::Pending(event_id)
+                            }
+                            Err(e) => {
+                                tracing::warn!("AUTH with {} failed: {:#}", relay_url, e);
+                                AuthState::Failed(format!("{:#}", e))
+                            }
+                        };
+                        task_shared.lock().unwrap().states.insert(relay_url, state);
+                    }
+                    RelayMessage::Ok { event_id, status, message } => {
+                        let Some(url) = pending.remove(&event_id) else {
+                            continue;
+                        };
+                        let state = if status {
+                            tracing::debug!("Authenticated with {}", url);
+                            AuthState::Authenticated
+                        } else {
+                            tracing::warn!("AUTH with {} rejected: {}", url, message);
+                            AuthState::Failed(format!("AUTH rejected: {}", message))
+                        };
+                        task_shared.lock().unwrap().states.insert(url, state);
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        Self { shared, task }
+    }
+
+    /// Answer AUTH challenges from these relays too.
+    pub fn enable(&self, urls: impl IntoIterator<Item = RelayUrl>) {
+        self.shared.lock().unwrap().enabled.extend(urls);
+    }
+
+    /// Wait up to `timeout` for the AUTH-enabled relays among `urls` to finish
+    /// authenticating. Relays that send no challenge shortly after connecting
+    /// are not waited for.
+    pub async fn wait(&self, urls: &[RelayUrl], timeout: Duration) {
+        let start = tokio::time::Instant::now();
+        let challenge_deadline = start + Duration::from_millis(CHALLENGE_WAIT_MILLIS).min(timeout);
+        let deadline = start + timeout;
+
+        loop {
+            let now = tokio::time::Instant::now();
+            let settled = {
+                let shared = self.shared.lock().unwrap();
+                urls.iter()
+                    .filter(|url| shared.enabled.contains(url))
+                    .all(|url| match shared.states.get(url) {
+                        Some(AuthState::Pending(_)) => false,
+                        Some(_) => true,
+                        None => now >= challenge_deadline,
+                    })
+            };
+            if settled || now >= deadline {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(POLL_MILLIS)).await;
+        }
+    }
+
+    /// The AUTH-enabled relays among `urls` that are authenticated, after waiting
+    /// up to `timeout` for any challenge in flight to be answered.
+    pub async fn authenticated(&self, urls: &[RelayUrl], timeout: Duration) -> Vec<RelayUrl> {
+        self.wait(urls, timeout).await;
+        let shared = self.shared.lock().unwrap();
+        urls.iter()
+            .filter(|url| shared.enabled.contains(*url))
+            .filter(|url| matches!(shared.states.get(*url), Some(AuthState::Authenticated)))
+            .cloned()
+            .collect()
+    }
+
+    /// Relays whose AUTH failed or was still unconfirmed when last checked.
+    pub fn failures(&self) -> Vec<AuthFailure> {
+        let shared = self.shared.lock().unwrap();
+        shared
+            .states
+            .iter()
+            .filter_map(|(url, state)| {
+                let error = match state {
+                    AuthState::Authenticated => return None,
+                    AuthState::Pending(_) => "No response to AUTH".to_string(),
+                    AuthState::Failed(error) => error.clone(),
+                };
+                Some(AuthFailure {
+                    url: url.to_string(),
+                    error,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Drop for RelayAuthenticator {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Whether a `CLOSED` or `OK` reason asks the client to authenticate first (NIP-42).
+pub fn is_auth_required(message: &str) -> bool {
+    message.starts_with("auth-required:")
+}
+
+/// Sign a kind 22242 AUTH event for `challenge` and send it, returning its ID
+/// so the relay's OK can be matched.
+async fn send_auth(
+    client: &Client,
+    signer: &dyn NostrSigner,
+    url: &RelayUrl,
+    challenge: &str,
+) -> Result<EventId> {
+    let pubkey = signer
+        .get_public_key()
+        .await
+        .context("Failed to get public key")?;
+    let event = sign_event(signer, EventBuilder::auth(challenge, url.clone()).build(pubkey)).await?;
+    let event_id = event.id;
+
+    let relay = client.relay(url).await?;
+    relay
+        .send_msg(ClientMessage::auth(event))
+        .context("Failed to send AUTH")?;
+
+    Ok(event_id)
+}
@@ -0,0 +1,159 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use nostr_sdk::RelayUrl;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// What a relay is used for.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum RelayRole {
+    /// Fetching and subscribing to group messages
+    Read,
+    /// Publishing group messages and welcomes
+    Write,
+    /// Publishing our key packages and looking up other users'
+    KeyPackages,
+    /// Receiving gift-wrapped welcomes
+    Inbox,
+}
+
+impl RelayRole {
+    pub const ALL: [RelayRole; 4] = [Self::Read, Self::Write, Self::KeyPackages, Self::Inbox];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::KeyPackages => "key-packages",
+            Self::Inbox => "inbox",
+        }
+    }
+}
+
+impl fmt::Display for RelayRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which relays carry a group's messages (kind 445).
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupRelays {
+    /// The relays in the group's own data extension (configured relays if it lists none)
+    #[default]
+    Group,
+    /// The group's relays plus the configured relays for the role
+    Union,
+}
+
+impl GroupRelays {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Group => "group",
+            Self::Union => "union",
+        }
+    }
+}
+
+/// A relay as written in `config.toml`: a bare URL (all roles, no AUTH) or
+/// `{ url = "...", roles = ["read", "inbox"], auth = true }`.
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RelayEntry {
+    Url(String),
+    Detailed(RelayTable),
+}
+
+/// The table form of a relay entry. Keys other than these are kept so they
+/// can be rejected by name: a misspelt `roles` would otherwise grant every role.
+#[derive(Deserialize, Clone)]
+pub struct RelayTable {
+    url: String,
+    #[serde(default)]
+    roles: Option<Vec<RelayRole>>,
+    #[serde(default)]
+    auth: bool,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+/// A validated relay and the roles it serves.
+#[derive(Serialize, Clone, Debug)]
+pub struct RelayConfig {
+    pub url: RelayUrl,
+    pub roles: BTreeSet<RelayRole>,
+    /// Answer the relay's NIP-42 AUTH challenges with our identity.
+    pub auth: bool,
+}
+
+impl RelayConfig {
+    /// Parse a bare URL, which serves every role.
+    pub fn parse(url: &str) -> Result<Self> {
+        Ok(Self {
+            url: parse_url(url)?,
+            roles: RelayRole::ALL.into_iter().collect(),
+            auth: false,
+        })
+    }
+
+    /// A relay used only for `role`.
+    pub fn for_role(url: RelayUrl, role: RelayRole, auth: bool) -> Self {
+        Self {
+            url,
+            roles: BTreeSet::from([role]),
+            auth,
+        }
+    }
+
+    pub fn from_entry(entry: &RelayEntry) -> Result<Self> {
+        match entry {
+            RelayEntry::Url(url) => Self::parse(url),
+            RelayEntry::Detailed(RelayTable { url, roles, auth, unknown }) => {
+                if let Some(key) = unknown.keys().next() {
+                    bail!("Unknown key '{}' in relay entry {} (expected url, roles, auth)", key, url);
+                }
+                let roles: BTreeSet<RelayRole> = match roles {
+                    Some(roles) => roles.iter().copied().collect(),
+                    None => RelayRole::ALL.into_iter().collect(),
+                };
+                if roles.is_empty() {
+                    bail!("Relay {} has no roles", url);
+                }
+                Ok(Self {
+                    url: parse_url(url)?,
+                    roles,
+                    auth: *auth,
+                })
+            }
+        }
+    }
+
+    pub fn has_role(&self, role: RelayRole) -> bool {
+        self.roles.contains(&role)
+    }
+
+    pub fn has_all_roles(&self) -> bool {
+        self.roles.len() == RelayRole::ALL.len()
+    }
+
+    /// Whether this relay can be written as a bare URL in the config file.
+    pub fn is_plain(&self) -> bool {
+        self.has_all_roles() && !self.auth
+    }
+}
+
+fn parse_url(url: &str) -> Result<RelayUrl> {
+    RelayUrl::parse(url).with_context(|| format!("Invalid relay URL: {}", url))
+}
+
+/// Relays serving at least one of `roles`.
+pub fn with_roles(relays: &[RelayConfig], roles: &[RelayRole]) -> Vec<RelayConfig> {
+    relays
+        .iter()
+        .filter(|relay| roles.iter().any(|role| relay.has_role(*role)))
+        .cloned()
+        .collect()
+}
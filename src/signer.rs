@@ -0,0 +1,245 @@
+use anyhow::{bail, Context, Result};
+use nostr_connect::prelude::*;
+use nostr_sdk::nips::nip59::RANGE_RANDOM_TIMESTAMP_TWEAK;
+use nostr_sdk::prelude::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::mdk_helper::{generate_keys, load_keys, parse_secret_key, save_keys};
+
+/// How long to wait for a remote signer to answer (it may need user approval).
+const BUNKER_TIMEOUT_SECS: u64 = 60;
+
+/// The Nostr identity used for signing and NIP-44/NIP-59 decryption.
+pub struct Identity {
+    pub signer: Arc<dyn NostrSigner>,
+    pub pubkey: PublicKey,
+    /// The local secret key, when the identity is not held by a remote signer.
+    pub keys: Option<Keys>,
+}
+
+impl Identity {
+    pub fn is_remote(&self) -> bool {
+        self.keys.is_none()
+    }
+}
+
+/// Load the identity from the configured NIP-46 bunker, or from the local key file.
+pub async fn load_identity(config: &Config) -> Result<Identity> {
+    let Some(uri) = &config.signer else {
+        let keys = load_keys(config)?;
+        return Ok(Identity {
+            signer: Arc::new(keys.clone()),
+            pubkey: keys.public_key(),
+            keys: Some(keys),
+        });
+    };
+
+    if !uri.starts_with("bunker://") {
+        bail!("Unsupported signer '{}': expected a bunker:// URI", uri);
+    }
+
+    let uri = NostrConnectURI::parse(uri).context("Invalid bunker URI")?;
+    let app_keys = load_app_keys(config)?;
+    // The bunker's relays get the same proxy as every other relay connection.
+    let relay_opts = config
+        .connect_options()?
+        .proxy
+        .map(|proxy| RelayOptions::new().connection_mode(ConnectionMode::Proxy(proxy)));
+    let connect = NostrConnect::new(uri, app_keys, Duration::from_secs(BUNKER_TIMEOUT_SECS), relay_opts)
+        .context("Failed to set up NIP-46 remote signer")?;
+    let signer: Arc<dyn NostrSigner> = Arc::new(connect);
+
+    let pubkey = signer
+        .get_public_key()
+        .await
+        .context("Remote signer did not return a public key")?;
+
+    Ok(Identity {
+        signer,
+        pubkey,
+        keys: None,
+    })
+}
+
+/// Client key used to talk to the bunker, kept beside the database so the
+/// bunker's authorization survives across invocations.
+fn load_app_keys(config: &Config) -> Result<Keys> {
+    let path = app_key_path(config);
+    if path.exists() {
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read bunker client key: {:?}", path))?;
+        return Ok(Keys::new(parse_secret_key(content.trim())?));
+    }
+
+    let keys = generate_keys();
+    save_keys(&keys, &path, None)?;
+    Ok(keys)
+}
+
+fn app_key_path(config: &Config) -> PathBuf {
+    config
+        .db_path
+        .parent()
+        .map(|p| p.join("bunker-client.key"))
+        .unwrap_or_else(|| PathBuf::from("bunker-client.key"))
+}
+
+pub async fn sign_event(signer: &dyn NostrSigner, unsigned: UnsignedEvent) -> Result<Event> {
+    signer
+        .sign_event(unsigned)
+        .await
+        .context("Failed to sign event")
+}
+
+/// Unwrap a NIP-59 gift-wrap, returning the seal author and the inner rumor.
+pub async fn extract_rumor(signer: &dyn NostrSigner, gift_wrap: &Event) -> Result<(PublicKey, UnsignedEvent)> {
+    if gift_wrap.kind != Kind::GiftWrap {
+        bail!("Event kind {} is not a gift-wrap", gift_wrap.kind.as_u16());
+    }
+
+    let seal_json = signer
+        .nip44_decrypt(&gift_wrap.pubkey, &gift_wrap.content)
+        .await
+        .context("Failed to decrypt gift-wrap")?;
+    let seal = Event::from_json(seal_json).context("Invalid seal")?;
+    seal.verify().context("Invalid seal signature")?;
+    if seal.kind != Kind::Seal {
+        bail!("Gift-wrap contains kind {}, expected a seal", seal.kind.as_u16());
+    }
+
+    let rumor_json = signer
+        .nip44_decrypt(&seal.pubkey, &seal.content)
+        .await
+        .context("Failed to decrypt seal")?;
+    let rumor = UnsignedEvent::from_json(rumor_json).context("Invalid rumor")?;
+    if rumor.pubkey != seal.pubkey {
+        bail!("Rumor author does not match seal author");
+    }
+
+    Ok((seal.pubkey, rumor))
+}
+
+/// Seal `rumor` with our identity and gift-wrap it for `receiver` (NIP-59).
+pub async fn gift_wrap(
+    signer: &dyn NostrSigner,
+    sender: &PublicKey,
+    receiver: &PublicKey,
+    mut rumor: UnsignedEvent,
+) -> Result<Event> {
+    rumor.ensure_id();
+
+    let content = signer
+        .nip44_encrypt(receiver, &rumor.as_json())
+        .await
+        .context("Failed to encrypt seal")?;
+    let seal = EventBuilder::new(Kind::Seal, content)
+        .custom_created_at(Timestamp::tweaked(RANGE_RANDOM_TIMESTAMP_TWEAK))
+        .build(*sender);
+    let seal = sign_event(signer, seal).await?;
+
+    EventBuilder::gift_wrap_from_seal(receiver, &seal, [])
+        .context("Failed to build gift-wrap")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for a NIP-46 bunker: only the `NostrSigner` methods are
+    /// reachable, so nothing can fall back to local key access.
+    #[derive(Debug)]
+    struct RemoteDouble(Keys);
+
+    impl NostrSigner for RemoteDouble {
+        fn backend(&self) -> SignerBackend {
+            SignerBackend::NostrConnect
+        }
+
+        fn get_public_key(&self) -> BoxedFuture<Result<PublicKey, SignerError>> {
+            self.0.get_public_key()
+        }
+
+        fn sign_event(&self, unsigned: UnsignedEvent) -> BoxedFuture<Result<Event, SignerError>> {
+            self.0.sign_event(unsigned)
+        }
+
+        fn nip04_encrypt<'a>(
+            &'a self,
+            public_key: &'a PublicKey,
+            content: &'a str,
+        ) -> BoxedFuture<'a, Result<String, SignerError>> {
+            self.0.nip04_encrypt(public_key, content)
+        }
+
+        fn nip04_decrypt<'a>(
+            &'a self,
+            public_key: &'a PublicKey,
+            encrypted_content: &'a str,
+        ) -> BoxedFuture<'a, Result<String, SignerError>> {
+            self.0.nip04_decrypt(public_key, encrypted_content)
+        }
+
+        fn nip44_encrypt<'a>(
+            &'a self,
+            public_key: &'a PublicKey,
+            content: &'a str,
+        ) -> BoxedFuture<'a, Result<String, SignerError>> {
+            self.0.nip44_encrypt(public_key, content)
+        }
+
+        fn nip44_decrypt<'a>(
+            &'a self,
+            public_key: &'a PublicKey,
+            payload: &'a str,
+        ) -> BoxedFuture<'a, Result<String, SignerError>> {
+            self.0.nip44_decrypt(public_key, payload)
+        }
+    }
+
+    fn remote() -> (RemoteDouble, PublicKey) {
+        let keys = Keys::generate();
+        let pubkey = keys.public_key();
+        (RemoteDouble(keys), pubkey)
+    }
+
+    #[tokio::test]
+    async fn gift_wrap_round_trips_through_remote_signer() {
+        let (sender, sender_pk) = remote();
+        let (receiver, receiver_pk) = remote();
+        let rumor = EventBuilder::new(Kind::Custom(444), "welcome").build(sender_pk);
+
+        let wrapped = gift_wrap(&sender, &sender_pk, &receiver_pk, rumor.clone())
+            .await
+            .unwrap();
+        assert_eq!(wrapped.kind, Kind::GiftWrap);
+        assert_ne!(wrapped.pubkey, sender_pk);
+        wrapped.verify().unwrap();
+
+        let (author, unwrapped) = extract_rumor(&receiver, &wrapped).await.unwrap();
+        assert_eq!(author, sender_pk);
+        assert_eq!(unwrapped.kind, rumor.kind);
+        assert_eq!(unwrapped.content, rumor.content);
+        assert_eq!(unwrapped.created_at, rumor.created_at);
+    }
+
+    #[tokio::test]
+    async fn extract_rumor_rejects_other_recipients() {
+        let (sender, sender_pk) = remote();
+        let (_, receiver_pk) = remote();
+        let (stranger, _) = remote();
+        let rumor = EventBuilder::new(Kind::Custom(444), "welcome").build(sender_pk);
+
+        let wrapped = gift_wrap(&sender, &sender_pk, &receiver_pk, rumor).await.unwrap();
+        assert!(extract_rumor(&stranger, &wrapped).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn extract_rumor_rejects_non_gift_wraps() {
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("hello").sign_with_keys(&keys).unwrap();
+        assert!(extract_rumor(&RemoteDouble(keys), &event).await.is_err());
+    }
+}
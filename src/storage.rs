@@ -0,0 +1,131 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use hkdf::Hkdf;
+use mdk_sqlite_storage::{EncryptionConfig, MdkSqliteStorage};
+use nostr_sdk::Keys;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::passphrase::{read_db_passphrase, read_new_db_passphrase};
+
+const IDENTITY_KDF_SALT: &[u8] = b"marmot-cli";
+const IDENTITY_KDF_INFO: &[u8] = b"sqlcipher database key";
+const SALT_LEN: usize = 16;
+const SCRYPT_LOG_N: u8 = 15;
+
+/// How the MDK database is encrypted at rest.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DbEncryption {
+    /// Plaintext SQLite
+    #[default]
+    None,
+    /// SQLCipher key derived from a passphrase (MDK_DB_PASSPHRASE or a prompt; scrypt, salt stored beside the database)
+    Passphrase,
+    /// SQLCipher key derived from the identity secret key (HKDF-SHA256)
+    Identity,
+}
+
+impl DbEncryption {
+    /// The name used in the config file and on the command line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Passphrase => "passphrase",
+            Self::Identity => "identity",
+        }
+    }
+}
+
+/// Open the MDK storage for `config`, deriving the SQLCipher key when encryption is enabled.
+/// `keys` is `None` when the identity lives in a remote signer.
+pub fn open_storage(config: &Config, keys: Option<&Keys>) -> Result<MdkSqliteStorage> {
+    match derive_db_key(config, keys, config.db_encryption)? {
+        Some(key) => MdkSqliteStorage::new_with_key(&config.db_path, EncryptionConfig::new(key))
+            .context("Failed to open encrypted MDK database (wrong key or unencrypted database?)"),
+        None => MdkSqliteStorage::new_unencrypted(&config.db_path)
+            .context("Failed to initialize MDK SQLite storage"),
+    }
+}
+
+/// The raw 32-byte SQLCipher key for `mode`, or `None` for unencrypted storage.
+pub fn derive_db_key(config: &Config, keys: Option<&Keys>, mode: DbEncryption) -> Result<Option<[u8; 32]>> {
+    match mode {
+        DbEncryption::None => Ok(None),
+        DbEncryption::Identity => {
+            let Some(keys) = keys else {
+                bail!("db_encryption = \"identity\" needs a local key; use \"passphrase\" with a remote signer");
+            };
+            let hkdf = Hkdf::<Sha256>::new(Some(IDENTITY_KDF_SALT), &keys.secret_key().to_secret_bytes());
+            let mut key = [0u8; 32];
+            hkdf.expand(IDENTITY_KDF_INFO, &mut key)
+                .map_err(|_| anyhow::anyhow!("Failed to derive database key"))?;
+            Ok(Some(key))
+        }
+        DbEncryption::Passphrase => {
+            // A database about to be created is locked with whatever is typed
+            // now, so the prompt asks twice.
+            let passphrase = if config.db_path.exists() {
+                read_db_passphrase()?
+            } else {
+                read_new_db_passphrase()?
+            };
+            passphrase_db_key(&config.db_path, &passphrase).map(Some)
+        }
+    }
+}
+
+/// The SQLCipher key for `passphrase` with the salt stored beside `db_path`.
+pub fn passphrase_db_key(db_path: &Path, passphrase: &str) -> Result<[u8; 32]> {
+    let salt = load_salt(db_path)?;
+    let params = scrypt::Params::new(SCRYPT_LOG_N, 8, 1, 32)
+        .map_err(|e| anyhow::anyhow!("Invalid scrypt parameters: {}", e))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), &salt, &params, &mut key)
+        .map_err(|e| anyhow::anyhow!("Failed to derive database key: {}", e))?;
+    Ok(key)
+}
+
+pub fn salt_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_else(|| "state.db".into());
+    name.push(".salt");
+    db_path.with_file_name(name)
+}
+
+/// Create the scrypt salt for a passphrase-encrypted database if it does not exist yet.
+pub fn ensure_salt(db_path: &Path) -> Result<()> {
+    let path = salt_path(db_path);
+    if path.exists() {
+        return Ok(());
+    }
+
+    let mut salt = vec![0u8; SALT_LEN];
+    getrandom::getrandom(&mut salt).map_err(|e| anyhow::anyhow!("Failed to generate salt: {}", e))?;
+    std::fs::write(&path, hex::encode(&salt))
+        .with_context(|| format!("Failed to write database salt: {:?}", path))?;
+
+    Ok(())
+}
+
+fn load_salt(db_path: &Path) -> Result<Vec<u8>> {
+    let path = salt_path(db_path);
+
+    if !path.exists() {
+        if db_path.exists() {
+            bail!(
+                "Database salt {:?} is missing; the passphrase-encrypted database cannot be opened",
+                path
+            );
+        }
+        ensure_salt(db_path)?;
+    }
+
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read database salt: {:?}", path))?;
+    hex::decode(content.trim()).context("Invalid database salt")
+}
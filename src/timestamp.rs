@@ -0,0 +1,168 @@
+use anyhow::{bail, Context, Result};
+
+/// Parse a relative duration such as `90s`, `15m`, `2h`, `3d` or `1w` into seconds.
+pub fn parse_duration_secs(input: &str) -> Option<u64> {
+    let input = input.trim();
+    let split = input.find(|c: char| !c.is_ascii_digit())?;
+    if split == 0 {
+        return None;
+    }
+
+    let (value, unit) = input.split_at(split);
+    let value: u64 = value.parse().ok()?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    value.checked_mul(multiplier)
+}
+
+/// Parse an RFC 3339 timestamp (e.g. `2024-05-01T12:00:00Z`) into unix seconds.
+pub fn parse_rfc3339(input: &str) -> Result<u64> {
+    let input = input.trim();
+    if !input.is_ascii() {
+        bail!("Not an RFC 3339 timestamp: {}", input);
+    }
+
+    let bytes = input.as_bytes();
+    let digits_at = [0, 1, 2, 3, 5, 6, 8, 9, 11, 12, 14, 15, 17, 18];
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+        || !digits_at.iter().all(|&i| bytes[i].is_ascii_digit())
+    {
+        bail!("Not an RFC 3339 timestamp: {}", input);
+    }
+
+    let field = |range: std::ops::Range<usize>| -> Result<i64> {
+        input[range]
+            .parse::<i64>()
+            .with_context(|| format!("Invalid RFC 3339 timestamp: {}", input))
+    };
+
+    let year = field(0..4)?;
+    let month = field(5..7)?;
+    let day = field(8..10)?;
+    let hour = field(11..13)?;
+    let minute = field(14..16)?;
+    let second = field(17..19)?;
+
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        bail!("Out-of-range RFC 3339 timestamp: {}", input);
+    }
+
+    // Skip fractional seconds, then parse the UTC offset.
+    let mut rest = &input[19..];
+    if let Some(frac) = rest.strip_prefix('.') {
+        let digits = frac.find(|c: char| !c.is_ascii_digit()).unwrap_or(frac.len());
+        rest = &frac[digits..];
+    }
+
+    let offset_secs = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6
+            && (rest.starts_with('+') || rest.starts_with('-'))
+            && &rest[3..4] == ":"
+            && rest[1..3].bytes().chain(rest[4..6].bytes()).all(|b| b.is_ascii_digit()) =>
+        {
+            let hours: i64 = rest[1..3].parse().with_context(|| format!("Invalid UTC offset: {}", rest))?;
+            let minutes: i64 = rest[4..6].parse().with_context(|| format!("Invalid UTC offset: {}", rest))?;
+            let sign = if rest.starts_with('-') { -1 } else { 1 };
+            sign * (hours * 3600 + minutes * 60)
+        }
+        _ => bail!("Invalid UTC offset in RFC 3339 timestamp: {}", input),
+    };
+
+    let secs = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset_secs;
+    u64::try_from(secs).with_context(|| format!("Timestamp before the unix epoch: {}", input))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration_secs("90s"), Some(90));
+        assert_eq!(parse_duration_secs("15m"), Some(900));
+        assert_eq!(parse_duration_secs("2h"), Some(7200));
+        assert_eq!(parse_duration_secs("3d"), Some(259_200));
+        assert_eq!(parse_duration_secs(" 1w "), Some(604_800));
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        assert_eq!(parse_duration_secs("h"), None);
+        assert_eq!(parse_duration_secs("10"), None);
+        assert_eq!(parse_duration_secs("10y"), None);
+        assert_eq!(parse_duration_secs("1.5h"), None);
+        assert_eq!(parse_duration_secs("-2h"), None);
+        assert_eq!(parse_duration_secs("99999999999999999999w"), None);
+        assert_eq!(parse_duration_secs("18446744073709551615w"), None);
+    }
+
+    #[test]
+    fn parses_rfc3339() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z").unwrap(), 0);
+        assert_eq!(parse_rfc3339("2024-05-01T12:00:00Z").unwrap(), 1_714_564_800);
+        assert_eq!(parse_rfc3339("2024-05-01t12:00:00z").unwrap(), 1_714_564_800);
+        assert_eq!(parse_rfc3339("2024-05-01 12:00:00.123Z").unwrap(), 1_714_564_800);
+        assert_eq!(parse_rfc3339("2024-05-01T14:30:00+02:30").unwrap(), 1_714_564_800);
+        assert_eq!(parse_rfc3339("2024-05-01T09:00:00-03:00").unwrap(), 1_714_564_800);
+        assert_eq!(parse_rfc3339("2024-02-29T00:00:00Z").unwrap(), 1_709_164_800);
+    }
+
+    #[test]
+    fn rejects_bad_rfc3339() {
+        for input in [
+            "",
+            "2024-05-01",
+            "2024-05-01T12:00:00",
+            "2024-05-01T12:00:0é0Z",
+            "2024-05-01T12:00:00+0é:00",
+            "2024-+5-01T12:00:00Z",
+            "2024-13-01T12:00:00Z",
+            "2024-02-30T12:00:00Z",
+            "2023-02-29T12:00:00Z",
+            "2024-04-31T12:00:00Z",
+            "2024-05-01T24:00:00Z",
+            "2024-05-01T12:00:00+0200",
+            "1969-12-31T23:59:59Z",
+        ] {
+            assert!(parse_rfc3339(input).is_err(), "accepted {:?}", input);
+        }
+    }
+}